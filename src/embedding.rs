@@ -2,8 +2,47 @@
 /// Generates lightweight semantic vectors (384 dims) from text using hashed TF-IDF.
 /// Zero external model, zero API, pure Rust. Enables cosine similarity search + RRF fusion.
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
 
-const VECTOR_DIM: usize = 384;
+pub const VECTOR_DIM: usize = 384;
+
+/// Weight of a term's 2nd and 3rd hashed positions relative to the 1st.
+pub const DEFAULT_POSITION_WEIGHTS: [f32; 2] = [0.7, 0.5];
+/// Weight of each hashed bigram.
+pub const DEFAULT_BIGRAM_WEIGHT: f32 = 0.3;
+
+/// Tunable constants of `embed_text`. Loaded from the `embedding_weights_json` config key at
+/// startup; changing them makes stored embeddings inconsistent until `--reembed`.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct EmbeddingWeights {
+    pub position: [f32; 2],
+    pub bigram: f32,
+}
+
+impl Default for EmbeddingWeights {
+    fn default() -> Self {
+        Self { position: DEFAULT_POSITION_WEIGHTS, bigram: DEFAULT_BIGRAM_WEIGHT }
+    }
+}
+
+static WEIGHTS: RwLock<EmbeddingWeights> = RwLock::new(EmbeddingWeights { position: DEFAULT_POSITION_WEIGHTS, bigram: DEFAULT_BIGRAM_WEIGHT });
+
+/// Replace the weights used by `embed_text` for the rest of the process.
+pub fn set_weights(weights: EmbeddingWeights) {
+    if let Ok(mut w) = WEIGHTS.write() { *w = weights; }
+}
+
+/// Synonym injection for stored content (`embed_text`) and for search queries (`embed_query`).
+static INDEX_SYNONYMS: AtomicBool = AtomicBool::new(true);
+static QUERY_SYNONYMS: AtomicBool = AtomicBool::new(true);
+
+/// Turn synonym expansion on or off at index time and at query time.
+pub fn set_synonyms(index: bool, query: bool) {
+    INDEX_SYNONYMS.store(index, Ordering::Relaxed);
+    QUERY_SYNONYMS.store(query, Ordering::Relaxed);
+}
 
 /// Generate a TF-IDF-style embedding vector from text.
 /// Uses feature hashing (hashing trick) to map any vocabulary to a fixed 384-dim vector.
@@ -25,17 +64,55 @@ fn get_synonyms(word: &str) -> Vec<&'static str> {
 }
 
 pub fn embed_text(text: &str) -> Vec<f32> {
+    let weights = WEIGHTS.read().map(|w| *w).unwrap_or_default();
+    embed_text_with(text, &weights, INDEX_SYNONYMS.load(Ordering::Relaxed))
+}
+
+/// Embedding of a search query; synonyms follow the query-time toggle.
+pub fn embed_query(text: &str) -> Vec<f32> {
+    let weights = WEIGHTS.read().map(|w| *w).unwrap_or_default();
+    embed_text_with(text, &weights, QUERY_SYNONYMS.load(Ordering::Relaxed))
+}
+
+/// `embed_text` with explicit weights and synonym injection.
+pub fn embed_text_with(text: &str, weights: &EmbeddingWeights, synonyms: bool) -> Vec<f32> {
     let mut tokens = tokenize(text);
     
     // Inject synonyms (Expert feature)
-    let mut extra_tokens = Vec::new();
-    for t in &tokens {
-        for syn in get_synonyms(t) {
-            extra_tokens.push(syn.to_string());
+    if synonyms {
+        let mut extra_tokens = Vec::new();
+        for t in &tokens {
+            for syn in get_synonyms(t) {
+                extra_tokens.push(syn.to_string());
+            }
         }
+        tokens.extend(extra_tokens);
     }
-    tokens.extend(extra_tokens);
 
+    let weighted: Vec<(String, f32)> = tokens.into_iter().map(|t| (t, 1.0)).collect();
+    embed_weighted(&weighted, weights)
+}
+
+/// Embedding for source code in `language`: identifiers are kept whole and also split into
+/// their camelCase/snake_case parts, and the language's keywords count for less.
+pub fn embed_code(text: &str, language: &str) -> Vec<f32> {
+    let weights = WEIGHTS.read().map(|w| *w).unwrap_or_default();
+    embed_weighted(&tokenize_code(text, language), &weights)
+}
+
+/// Identifier parts of `text` that the FTS tokenizer would not produce on its own
+/// (camelCase pieces), space-separated, for indexing next to a snippet's content.
+pub fn code_index_terms(text: &str, language: &str) -> String {
+    let whole: std::collections::HashSet<String> = tokenize(text).into_iter().collect();
+    let mut seen = std::collections::HashSet::new();
+    tokenize_code(text, language).into_iter()
+        .map(|(t, _)| t)
+        .filter(|t| !whole.contains(t) && !t.contains('_') && seen.insert(t.clone()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn embed_weighted(tokens: &[(String, f32)], weights: &EmbeddingWeights) -> Vec<f32> {
     if tokens.is_empty() {
         return vec![0.0; VECTOR_DIM];
     }
@@ -43,8 +120,8 @@ pub fn embed_text(text: &str) -> Vec<f32> {
     // Term frequency
     let mut tf: HashMap<&str, f32> = HashMap::new();
     let total = tokens.len() as f32;
-    for t in &tokens {
-        *tf.entry(t.as_str()).or_default() += 1.0;
+    for (t, w) in tokens {
+        *tf.entry(t.as_str()).or_default() += w;
     }
 
     // Build vector using feature hashing
@@ -61,21 +138,21 @@ pub fn embed_text(text: &str) -> Vec<f32> {
         let h3 = hash_term(term, 2) % VECTOR_DIM;
 
         // Sign from hash to spread positive/negative
-        let sign1 = if hash_term(term, 3) % 2 == 0 { 1.0 } else { -1.0 };
-        let sign2 = if hash_term(term, 4) % 2 == 0 { 1.0 } else { -1.0 };
-        let sign3 = if hash_term(term, 5) % 2 == 0 { 1.0 } else { -1.0 };
+        let sign1 = if hash_term(term, 3).is_multiple_of(2) { 1.0 } else { -1.0 };
+        let sign2 = if hash_term(term, 4).is_multiple_of(2) { 1.0 } else { -1.0 };
+        let sign3 = if hash_term(term, 5).is_multiple_of(2) { 1.0 } else { -1.0 };
 
         vec[h1] += weight * sign1;
-        vec[h2] += weight * sign2 * 0.7;
-        vec[h3] += weight * sign3 * 0.5;
+        vec[h2] += weight * sign2 * weights.position[0];
+        vec[h3] += weight * sign3 * weights.position[1];
     }
 
     // Also hash bigrams for phrase-level semantics
     for pair in tokens.windows(2) {
-        let bigram = format!("{}_{}", pair[0], pair[1]);
+        let bigram = format!("{}_{}", pair[0].0, pair[1].0);
         let h = hash_term(&bigram, 6) % VECTOR_DIM;
-        let sign = if hash_term(&bigram, 7) % 2 == 0 { 1.0 } else { -1.0 };
-        vec[h] += sign * 0.3;
+        let sign = if hash_term(&bigram, 7).is_multiple_of(2) { 1.0 } else { -1.0 };
+        vec[h] += sign * weights.bigram;
     }
 
     // L2 normalize
@@ -84,16 +161,119 @@ pub fn embed_text(text: &str) -> Vec<f32> {
 }
 
 /// Cosine similarity between two normalized vectors. Range: -1 to 1.
+/// 0 for mismatched lengths or any non-finite component, so scores always sort cleanly.
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() || a.is_empty() { return 0.0; }
+    if a.iter().chain(b).any(|x| !x.is_finite()) { return 0.0; }
     a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
 }
 
+/// Spherical k-means over normalized vectors (cosine similarity as closeness).
+/// Deterministic: seeds with the first vector, then repeatedly the vector farthest from all
+/// chosen seeds. Returns each vector's cluster index and the normalized centroids.
+pub fn kmeans(vectors: &[Vec<f32>], k: usize, max_iter: usize) -> (Vec<usize>, Vec<Vec<f32>>) {
+    let k = k.min(vectors.len());
+    if k == 0 { return (vec![0; vectors.len()], Vec::new()); }
+
+    let mut centroids = vec![vectors[0].clone()];
+    while centroids.len() < k {
+        let farthest = vectors.iter().enumerate()
+            .map(|(i, v)| (i, centroids.iter().map(|c| cosine_similarity(v, c)).fold(f32::MIN, f32::max)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        centroids.push(vectors[farthest].clone());
+    }
+
+    let nearest = |v: &[f32], centroids: &[Vec<f32>]| -> usize {
+        centroids.iter().enumerate()
+            .map(|(i, c)| (i, cosine_similarity(v, c)))
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)))
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    };
+
+    let mut assignments: Vec<usize> = vectors.iter().map(|v| nearest(v, &centroids)).collect();
+    for _ in 0..max_iter {
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            let mut sum = vec![0.0f32; centroid.len()];
+            let mut members = 0;
+            for (v, _) in vectors.iter().zip(&assignments).filter(|(_, a)| **a == c) {
+                for (s, x) in sum.iter_mut().zip(v) { *s += x; }
+                members += 1;
+            }
+            // An emptied cluster keeps its old centroid
+            if members > 0 {
+                normalize_vec(&mut sum);
+                *centroid = sum;
+            }
+        }
+        let next: Vec<usize> = vectors.iter().map(|v| nearest(v, &centroids)).collect();
+        if next == assignments { break; }
+        assignments = next;
+    }
+    (assignments, centroids)
+}
+
 /// Reciprocal Rank Fusion: combines BM25 and vector search rankings.
 /// k=60 is standard. Returns merged score (higher = better).
 pub fn rrf_score(bm25_rank: usize, vector_rank: usize) -> f64 {
-    let k = 60.0;
-    (1.0 / (k + bm25_rank as f64)) + (1.0 / (k + vector_rank as f64))
+    rrf_component(bm25_rank) + rrf_component(vector_rank)
+}
+
+/// Contribution of a single ranked list to an RRF score (k = 60).
+pub fn rrf_component(rank: usize) -> f64 {
+    1.0 / (60.0 + rank as f64)
+}
+
+/// Common abbreviations mapped to the name entity extraction and tags usually use.
+fn canonical_alias(word: &str) -> &str {
+    match word {
+        "k8s" | "kube" => "kubernetes",
+        "pg" | "postgres" | "psql" => "postgresql",
+        "js" => "javascript",
+        "ts" => "typescript",
+        "mongo" => "mongodb",
+        "py" => "python",
+        "gh" => "github",
+        other => other,
+    }
+}
+
+/// Typo/variant-tolerant equality for tags, entity values and project names: case-insensitive,
+/// alias-aware (k8s = kubernetes), prefix variants that only add an inflection or keep 75% of
+/// the length (deploy = deployed, postgres = postgresql, but not java = javascript), synonyms
+/// from the embedding map, and small edit distance (1 for 5-char words, 2 beyond, none shorter).
+pub fn fuzzy_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.trim().to_lowercase(), b.trim().to_lowercase());
+    if a.is_empty() || b.is_empty() { return false; }
+    let (a, b) = (canonical_alias(&a).to_string(), canonical_alias(&b).to_string());
+    if a == b { return true; }
+    let (short, long) = if a.chars().count() <= b.chars().count() { (&a, &b) } else { (&b, &a) };
+    let (shorter, longer) = (short.chars().count(), long.chars().count());
+    if shorter >= 4 {
+        if let Some(suffix) = long.strip_prefix(short.as_str()) {
+            if matches!(suffix, "s" | "es" | "ed" | "ing" | "er") || shorter * 4 >= longer * 3 { return true; }
+        }
+    }
+    if get_synonyms(&a).contains(&b.as_str()) || get_synonyms(&b).contains(&a.as_str()) { return true; }
+    let max_edits = match shorter { 0..=4 => 0, 5 => 1, _ => 2 };
+    max_edits > 0 && edit_distance(&a, &b) <= max_edits
+}
+
+/// Levenshtein distance over chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
 }
 
 /// Serialize embedding vector to bytes for SQLite BLOB storage.
@@ -102,9 +282,11 @@ pub fn vec_to_blob(v: &[f32]) -> Vec<u8> {
 }
 
 /// Deserialize bytes from SQLite BLOB to embedding vector.
+/// Non-finite values (from a corrupted blob) become 0.
 pub fn blob_to_vec(blob: &[u8]) -> Vec<f32> {
     blob.chunks_exact(4)
         .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .map(|f| if f.is_finite() { f } else { 0.0 })
         .collect()
 }
 
@@ -118,7 +300,68 @@ fn tokenize(text: &str) -> Vec<String> {
         .collect()
 }
 
+/// Weight of a language keyword relative to an identifier in `embed_code`.
+const CODE_KEYWORD_WEIGHT: f32 = 0.3;
+
+/// Code tokens with weights: each identifier lowercased whole, then its camelCase/snake_case
+/// parts when it has several; keywords of `language` weigh `CODE_KEYWORD_WEIGHT`.
+fn tokenize_code(text: &str, language: &str) -> Vec<(String, f32)> {
+    let keywords = language_keywords(language);
+    let mut tokens = Vec::new();
+    for ident in text.split(|c: char| !c.is_alphanumeric() && c != '_').filter(|w| w.len() >= 2) {
+        let whole = ident.to_lowercase();
+        let weight = if keywords.contains(&whole.as_str()) { CODE_KEYWORD_WEIGHT } else { 1.0 };
+        tokens.push((whole, weight));
+        let parts = split_identifier(ident);
+        if parts.len() > 1 {
+            tokens.extend(parts.into_iter().filter(|p| p.len() >= 2).map(|p| (p, 1.0)));
+        }
+    }
+    tokens
+}
+
+/// `getUserID_v2` -> ["get", "user", "id", "v2"]; `HTTPServer` -> ["http", "server"].
+fn split_identifier(ident: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    for piece in ident.split('_').filter(|p| !p.is_empty()) {
+        let chars: Vec<char> = piece.chars().collect();
+        let mut current = String::new();
+        for (i, &c) in chars.iter().enumerate() {
+            let boundary = i > 0 && c.is_uppercase() && (chars[i - 1].is_lowercase()
+                || chars.get(i + 1).is_some_and(|n| n.is_lowercase()) && chars[i - 1].is_uppercase());
+            if boundary && !current.is_empty() {
+                parts.push(std::mem::take(&mut current).to_lowercase());
+            }
+            current.push(c);
+        }
+        if !current.is_empty() { parts.push(current.to_lowercase()); }
+    }
+    parts
+}
+
+/// Reserved words of common languages; unknown languages get a small shared set.
+fn language_keywords(language: &str) -> &'static [&'static str] {
+    match canonical_alias(&language.trim().to_lowercase()) {
+        "rust" | "rs" => &["fn", "let", "mut", "pub", "impl", "struct", "enum", "trait", "use", "mod", "match", "if", "else",
+            "for", "while", "loop", "return", "self", "crate", "super", "where", "as", "in", "ref", "move", "async", "await",
+            "const", "static", "type", "unsafe", "dyn", "some", "none", "ok", "err", "true", "false"],
+        "python" => &["def", "class", "import", "from", "return", "if", "elif", "else", "for", "while", "in", "is", "not",
+            "and", "or", "with", "as", "try", "except", "finally", "raise", "lambda", "yield", "pass", "self", "none",
+            "true", "false", "async", "await"],
+        "javascript" | "typescript" => &["function", "const", "let", "var", "return", "if", "else", "for", "while", "import",
+            "export", "from", "default", "class", "extends", "new", "this", "async", "await", "try", "catch", "throw",
+            "typeof", "interface", "type", "null", "undefined", "true", "false"],
+        "go" | "golang" => &["func", "package", "import", "var", "const", "type", "struct", "interface", "return", "if",
+            "else", "for", "range", "go", "defer", "chan", "map", "nil", "err", "true", "false"],
+        "sql" => &["select", "from", "where", "and", "or", "insert", "into", "values", "update", "set", "delete", "join",
+            "on", "as", "group", "by", "order", "limit", "create", "table", "index", "null", "not"],
+        _ => &["if", "else", "for", "while", "return", "function", "class", "import", "const", "let", "var", "true",
+            "false", "null", "nil", "none"],
+    }
+}
+
 fn normalize_vec(v: &mut [f32]) {
+    for x in v.iter_mut().filter(|x| !x.is_finite()) { *x = 0.0; }
     let norm: f32 = v.iter().map(|x| x * x).sum::<f32>().sqrt();
     if norm > 1e-8 {
         for x in v.iter_mut() { *x /= norm; }
@@ -149,6 +392,77 @@ mod tests {
         assert!(sim_related > sim_unrelated, "Related texts should have higher similarity");
     }
 
+    #[test]
+    fn test_similar_texts_across_weights() {
+        let settings = [
+            EmbeddingWeights::default(),
+            EmbeddingWeights { position: [0.0, 0.0], bigram: 0.0 },
+            EmbeddingWeights { position: [1.0, 1.0], bigram: 1.0 },
+            EmbeddingWeights { position: [0.3, 0.1], bigram: 0.6 },
+        ];
+        for w in &settings {
+            let v1 = embed_text_with("authentication login Supabase auth JWT", w, true);
+            let v2 = embed_text_with("user login authentication with JWT tokens", w, true);
+            let v3 = embed_text_with("CSS grid layout flexbox styling", w, true);
+            assert!(cosine_similarity(&v1, &v2) > cosine_similarity(&v1, &v3), "related texts should stay closer with {:?}", w);
+        }
+    }
+
+    #[test]
+    fn test_synonyms_can_be_disabled() {
+        let w = EmbeddingWeights::default();
+        // "jwt" shares no token with "session" except through synonym injection
+        let with = cosine_similarity(&embed_text_with("jwt", &w, true), &embed_text_with("session", &w, true));
+        let without = cosine_similarity(&embed_text_with("jwt", &w, false), &embed_text_with("session", &w, false));
+        assert!(with > 0.1, "with synonyms: {}", with);
+        assert!(without < with, "without {} vs with {}", without, with);
+    }
+
+    #[test]
+    fn test_fuzzy_eq() {
+        assert!(fuzzy_eq("postgres", "postgresql"));
+        assert!(fuzzy_eq("k8s", "Kubernetes"));
+        assert!(fuzzy_eq("sveltkit", "sveltekit"));
+        assert!(fuzzy_eq("db", "sqlite"));
+        assert!(!fuzzy_eq("rust", "react"));
+        assert!(!fuzzy_eq("go", "js"));
+        assert!(fuzzy_eq("deploy", "deploying"));
+        assert!(fuzzy_eq("my-app", "myapp"));
+        assert!(!fuzzy_eq("java", "javascript"));
+        assert!(!fuzzy_eq("type", "typescript"));
+        assert!(!fuzzy_eq("app", "api"));
+    }
+
+    #[test]
+    fn test_code_tokenization() {
+        assert_eq!(split_identifier("getUserID_v2"), ["get", "user", "id", "v2"]);
+        assert_eq!(split_identifier("HTTPServer"), ["http", "server"]);
+        assert_eq!(code_index_terms("fn parseConfigFile(path: &str)", "rust"), "parse config file");
+
+        let query = embed_text("parse config file");
+        let code = embed_code("fn parseConfigFile(path: &str) -> Result<Config>", "rust");
+        let plain = embed_text("fn parseConfigFile(path: &str) -> Result<Config>");
+        assert!(cosine_similarity(&query, &code) > cosine_similarity(&query, &plain));
+    }
+
+    #[test]
+    fn test_kmeans_separates_topics() {
+        let texts = [
+            "postgres database index query plan",
+            "database query slow postgres index",
+            "react component state hooks render",
+            "react hooks render component props",
+        ];
+        let vectors: Vec<Vec<f32>> = texts.iter().map(|t| embed_text(t)).collect();
+        let (assignments, centroids) = kmeans(&vectors, 2, 20);
+        assert_eq!(centroids.len(), 2);
+        assert_eq!(assignments[0], assignments[1]);
+        assert_eq!(assignments[2], assignments[3]);
+        assert_ne!(assignments[0], assignments[2]);
+        // k is capped at the number of vectors
+        assert_eq!(kmeans(&vectors[..1], 3, 20).1.len(), 1);
+    }
+
     #[test]
     fn test_blob_roundtrip() {
         let v = embed_text("test embedding roundtrip");
@@ -159,4 +473,14 @@ mod tests {
             assert!((a - b).abs() < 1e-7);
         }
     }
+
+    #[test]
+    fn test_non_finite_blob_is_sanitized() {
+        let blob = vec_to_blob(&[f32::NAN, 0.6, f32::INFINITY, 0.8, f32::NEG_INFINITY]);
+        let restored = blob_to_vec(&blob);
+        assert_eq!(restored, [0.0, 0.6, 0.0, 0.8, 0.0]);
+        let v = [0.0, 0.6, 0.0, 0.8, 0.0];
+        assert!((cosine_similarity(&restored, &v) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[f32::NAN, 1.0, 0.0, 0.0, 0.0], &v), 0.0);
+    }
 }
@@ -1,6 +1,7 @@
 /// MemoryPilot v3.0 — Garbage Collection & Memory Compression.
 /// Heuristic-based cleanup: merges old low-importance memories, keeps base dense.
 /// Runs as background thread or on-demand via tool.
+use std::collections::{HashMap, HashSet};
 
 /// Result of a GC cycle.
 #[derive(Debug, Clone, serde::Serialize)]
@@ -9,8 +10,14 @@ pub struct GcReport {
     pub groups_merged: usize,
     pub memories_compressed: usize,
     pub orphan_links_removed: usize,
+    /// Low-signal `relates_to` links dropped by `Database::prune_links` (would be, in a dry run);
+    /// 0 unless `GcConfig::prune_links` is on.
+    pub links_pruned: usize,
     pub db_size_before: u64,
     pub db_size_after: u64,
+    /// The client cancelled mid-run; counts reflect the work done before stopping.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
 }
 
 /// Configuration for GC behavior.
@@ -23,6 +30,41 @@ pub struct GcConfig {
     pub max_merge_group: usize,
     /// Kinds eligible for compression.
     pub compressible_kinds: Vec<String>,
+    /// GC weight per kind (higher = more expendable). Unknown kinds weigh 0.5.
+    /// Overridable via the `gc_kind_weights_json` config key and custom kinds.
+    pub kind_weights: HashMap<String, f64>,
+    /// Placed before each bullet of a merged memory (`gc_merge_separator` config key).
+    pub merge_separator: String,
+    /// Memories accessed within this many days are never candidates, whatever their age
+    /// (`gc_protect_accessed_days` config key; 0 disables).
+    pub protect_accessed_days: i64,
+    /// Whether a GC run also calls `Database::prune_links` (`gc_prune_links` config key or the
+    /// `run_gc` `prune_links` flag). Off by default: it deletes graph edges, not memories.
+    pub prune_links: bool,
+    /// `relates_to` links whose shared entities are all in more than this many memories are
+    /// pruned (`gc_prune_link_max_df` config key; 0 disables).
+    pub prune_link_max_df: i64,
+    /// `relates_to` links whose two memories' importances sum below this are pruned
+    /// (`gc_prune_link_min_importance` config key; 0 disables).
+    pub prune_link_min_importance: i32,
+}
+
+/// Default bullet separator for merged memories: one "- " bullet per line.
+pub const DEFAULT_MERGE_SEPARATOR: &str = "\n- ";
+
+/// Built-in kind weights used when no override is configured.
+pub fn default_kind_weights() -> HashMap<String, f64> {
+    [
+        ("todo", 1.2),       // Completed/stale todos are prime candidates
+        ("bug", 1.0),        // Old bugs are likely resolved
+        ("note", 0.9),       // Notes may be transient
+        ("snippet", 0.6),    // Snippets are often reusable
+        ("decision", 0.3),   // Decisions are important context
+        ("preference", 0.2), // Preferences should persist
+        ("pattern", 0.2),    // Patterns are reusable
+        ("fact", 0.4),       // Facts may become outdated
+        ("credential", 0.1), // Credentials should persist
+    ].into_iter().map(|(k, w)| (k.to_string(), w)).collect()
 }
 
 impl Default for GcConfig {
@@ -34,37 +76,25 @@ impl Default for GcConfig {
             compressible_kinds: vec![
                 "bug".into(), "snippet".into(), "note".into(), "todo".into(),
             ],
+            kind_weights: default_kind_weights(),
+            merge_separator: DEFAULT_MERGE_SEPARATOR.to_string(),
+            protect_accessed_days: 14,
+            prune_links: false,
+            prune_link_max_df: 50,
+            prune_link_min_importance: 4,
         }
     }
 }
 
 /// Merge a group of related old memories into a single condensed memory.
-/// Pure heuristic summarization — no LLM needed.
-pub fn merge_memories(contents: &[String], kind: &str, project: Option<&str>) -> String {
+/// Pure heuristic summarization — no LLM needed. `separator` precedes each bullet.
+pub fn merge_memories(contents: &[String], kind: &str, project: Option<&str>, separator: &str) -> String {
     if contents.len() == 1 {
         return contents[0].clone();
     }
 
-    // Count word frequency across all memories (document frequency, not raw)
-    let mut word_freq: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
-    for c in contents {
-        let mut seen: std::collections::HashSet<String> = std::collections::HashSet::new();
-        for w in c.split_whitespace() {
-            let w = w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
-            if w.len() > 3 && !is_stopword(&w) && seen.insert(w.clone()) {
-                *word_freq.entry(w).or_default() += 1;
-            }
-        }
-    }
-
     // Top 5 keywords = subject
-    let mut top_words: Vec<(String, usize)> = word_freq.into_iter().collect();
-    top_words.sort_by(|a, b| b.1.cmp(&a.1));
-    let subject: String = top_words.iter()
-        .take(5)
-        .map(|(w, _)| w.as_str())
-        .collect::<Vec<_>>()
-        .join(", ");
+    let subject = top_keywords(contents, 5).join(", ");
 
     // Build condensed summary
     let project_prefix = project.map(|p| format!("[{}] ", p)).unwrap_or_default();
@@ -83,23 +113,43 @@ pub fn merge_memories(contents: &[String], kind: &str, project: Option<&str>) ->
             // Take first sentence or first 120 chars
             let end = trimmed.find(". ")
                 .map(|i| i + 1)
-                .unwrap_or_else(|| trimmed.len().min(120));
+                .unwrap_or_else(|| trimmed.char_indices().nth(120).map(|(i, _)| i).unwrap_or(trimmed.len()));
             let sentence = &trimmed[..end];
-            if sentence.len() > 5 { Some(format!("- {}", sentence)) } else { None }
+            if sentence.len() > 5 { Some(format!("{}{}", separator, sentence)) } else { None }
         })
         .take(8) // Max 8 bullets
         .collect();
 
     format!(
-        "{}[MERGED] {} related to: {}. ({} items compressed)\n{}",
+        "{}[MERGED] {} related to: {}. ({} items compressed){}",
         project_prefix, kind_label, subject,
-        contents.len(), bullets.join("\n")
+        contents.len(), bullets.concat()
     )
 }
 
+/// The `n` words appearing in the most of `contents` (document frequency), skipping short
+/// words and stopwords. Ties go to the alphabetically first word.
+pub fn top_keywords(contents: &[String], n: usize) -> Vec<String> {
+    let mut word_freq: HashMap<String, usize> = HashMap::new();
+    for c in contents {
+        let mut seen: HashSet<String> = HashSet::new();
+        for w in c.split_whitespace() {
+            let w = w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+            if w.len() > 3 && !is_stopword(&w) && seen.insert(w.clone()) {
+                *word_freq.entry(w).or_default() += 1;
+            }
+        }
+    }
+    let mut top_words: Vec<(String, usize)> = word_freq.into_iter().collect();
+    top_words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top_words.into_iter().take(n).map(|(w, _)| w).collect()
+}
+
 /// Score a memory for GC candidacy (higher = more likely to be collected).
-/// Returns 0.0-1.0.
-pub fn gc_score(importance: i32, age_days: i64, kind: &str, _config: &GcConfig) -> f64 {
+/// Returns 0.0-1.0; always 0.0 when last accessed within `protect_accessed_days`.
+pub fn gc_score(importance: i32, age_days: i64, days_since_access: Option<i64>, kind: &str, config: &GcConfig) -> f64 {
+    if days_since_access.is_some_and(|d| d < config.protect_accessed_days) { return 0.0; }
+
     // Base score from importance (lower importance = higher GC score)
     let importance_score = 1.0 - ((importance as f64 - 1.0) / 4.0); // 1->1.0, 5->0.0
 
@@ -107,18 +157,7 @@ pub fn gc_score(importance: i32, age_days: i64, kind: &str, _config: &GcConfig)
     let age_factor = (age_days as f64 / 365.0).min(1.0);
 
     // Kind weight (some kinds are more expendable)
-    let kind_weight = match kind {
-        "todo" => 1.2,       // Completed/stale todos are prime candidates
-        "bug" => 1.0,        // Old bugs are likely resolved
-        "note" => 0.9,       // Notes may be transient
-        "snippet" => 0.6,    // Snippets are often reusable
-        "decision" => 0.3,   // Decisions are important context
-        "preference" => 0.2, // Preferences should persist
-        "pattern" => 0.2,    // Patterns are reusable
-        "fact" => 0.4,       // Facts may become outdated
-        "credential" => 0.1, // Credentials should persist
-        _ => 0.5,
-    };
+    let kind_weight = config.kind_weights.get(kind).copied().unwrap_or(0.5);
 
     (importance_score * 0.4 + age_factor * 0.3 + kind_weight * 0.3).min(1.0)
 }
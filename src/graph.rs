@@ -53,19 +53,18 @@ pub fn extract_entities(content: &str, project: Option<&str>) -> Vec<Entity> {
     // 3. File paths (detect patterns like src/foo/bar.ts, lib/components/X.svelte)
     for word in content.split_whitespace() {
         let w = word.trim_matches(|c: char| !c.is_alphanumeric() && c != '/' && c != '.' && c != '_' && c != '-');
-        if w.contains('/') && w.contains('.') && w.len() > 4 {
-            if seen.insert(format!("file:{}", w.to_lowercase())) {
-                entities.push(Entity { kind: "file", value: w.to_string() });
-            }
+        if w.contains('/') && w.contains('.') && w.len() > 4
+            && seen.insert(format!("file:{}", w.to_lowercase()))
+        {
+            entities.push(Entity { kind: "file", value: w.to_string() });
         }
         // Also detect .svelte, .ts, .rs files without path
         if (w.ends_with(".svelte") || w.ends_with(".ts") || w.ends_with(".tsx")
             || w.ends_with(".rs") || w.ends_with(".py") || w.ends_with(".js"))
             && w.len() > 4 && !w.starts_with('.')
+            && seen.insert(format!("file:{}", w.to_lowercase()))
         {
-            if seen.insert(format!("file:{}", w.to_lowercase())) {
-                entities.push(Entity { kind: "file", value: w.to_string() });
-            }
+            entities.push(Entity { kind: "file", value: w.to_string() });
         }
     }
 
@@ -78,10 +77,9 @@ pub fn extract_entities(content: &str, project: Option<&str>) -> Vec<Entity> {
                 if w.len() > 2 && (w.chars().next().map(|c| c.is_uppercase()).unwrap_or(false)
                     || w.contains('-') || w.contains('_'))
                     && lower_contains_near(&lower, hint, &w.to_lowercase(), 50)
+                    && seen.insert(format!("component:{}", w.to_lowercase()))
                 {
-                    if seen.insert(format!("component:{}", w.to_lowercase())) {
-                        entities.push(Entity { kind: "component", value: w.to_string() });
-                    }
+                    entities.push(Entity { kind: "component", value: w.to_string() });
                 }
             }
         }
@@ -90,6 +88,19 @@ pub fn extract_entities(content: &str, project: Option<&str>) -> Vec<Entity> {
     entities
 }
 
+/// Tag candidates from a memory's entities: technologies and components, lowercased, in
+/// extraction order. Projects and files make poor tags and are left out.
+pub fn entity_tags(content: &str, project: Option<&str>) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for e in extract_entities(content, project) {
+        let tag = e.value.to_lowercase();
+        if matches!(e.kind, "tech" | "component") && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
 /// Infer relationship type between two memories based on their kinds.
 pub fn infer_relation(source_kind: &str, target_kind: &str) -> &'static str {
     match (source_kind, target_kind) {
@@ -105,12 +116,23 @@ pub fn infer_relation(source_kind: &str, target_kind: &str) -> &'static str {
     }
 }
 
+/// How ubiquitous an entity kind tends to be, used to scale its document frequency when
+/// ranking which shared entities are worth linking through (lower = more specific).
+pub fn kind_spread(kind: &str) -> f64 {
+    match kind {
+        "file" => 0.5,
+        "component" => 0.75,
+        "person" => 1.0,
+        "tech" => 1.5,
+        _ => 2.0, // project: shared by every memory of the project
+    }
+}
+
 /// Check if two substrings appear within `distance` chars of each other.
 fn lower_contains_near(text: &str, a: &str, b: &str, distance: usize) -> bool {
     if let Some(pos_a) = text.find(a) {
         if let Some(pos_b) = text.find(b) {
-            let diff = if pos_a > pos_b { pos_a - pos_b } else { pos_b - pos_a };
-            return diff <= distance;
+            return pos_a.abs_diff(pos_b) <= distance;
         }
     }
     false
@@ -0,0 +1,52 @@
+/// MemoryPilot — Leveled diagnostics on stderr.
+/// `MEMORYPILOT_LOG` = error | warn (default) | info | debug | off. One logfmt line per event;
+/// never stdout, which carries the JSON-RPC stream.
+use std::io::Write;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level { Error, Warn, Info, Debug }
+
+impl Level {
+    fn label(self) -> &'static str {
+        match self { Level::Error => "ERROR", Level::Warn => "WARN", Level::Info => "INFO", Level::Debug => "DEBUG" }
+    }
+}
+
+/// Most verbose level emitted; None = logging off.
+fn max_level() -> Option<Level> {
+    static MAX: OnceLock<Option<Level>> = OnceLock::new();
+    *MAX.get_or_init(|| match std::env::var("MEMORYPILOT_LOG").unwrap_or_default().trim().to_lowercase().as_str() {
+        "off" | "none" => None,
+        "error" => Some(Level::Error),
+        "info" => Some(Level::Info),
+        "debug" | "trace" => Some(Level::Debug),
+        _ => Some(Level::Warn),
+    })
+}
+
+pub fn enabled(level: Level) -> bool {
+    max_level().is_some_and(|max| level <= max)
+}
+
+/// Write `ts LEVEL message key=value ...`; values with spaces, quotes or `=` are quoted.
+pub fn emit(level: Level, message: &str, fields: &[(&str, String)]) {
+    let mut line = format!("{} {} {}", chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"), level.label(), message);
+    for (key, value) in fields {
+        if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+            line.push_str(&format!(" {}={:?}", key, value));
+        } else {
+            line.push_str(&format!(" {}={}", key, value));
+        }
+    }
+    let _ = writeln!(std::io::stderr().lock(), "{}", line);
+}
+
+/// `log_at!(Info, "gc", merged = n, ms = elapsed)`: fields are only formatted when the level is on.
+macro_rules! log_at {
+    ($level:ident, $message:expr $(, $key:ident = $value:expr)* $(,)?) => {
+        if $crate::logging::enabled($crate::logging::Level::$level) {
+            $crate::logging::emit($crate::logging::Level::$level, $message, &[$((stringify!($key), format!("{}", $value))),*]);
+        }
+    };
+}
@@ -1,6 +1,9 @@
+#![recursion_limit = "256"] // tools::tool_definitions is one large json! literal
 /// MemoryPilot v3.1 — God-Tier MCP memory server.
 /// Hybrid search (BM25+TF-IDF RRF), Knowledge Graph, GC, Project Brain, File Watcher.
 /// (c) SOFLUTION LTD — MIT License
+#[macro_use]
+mod logging;
 mod db;
 mod protocol;
 mod tools;
@@ -14,118 +17,389 @@ use protocol::{JsonRpcRequest, JsonRpcResponse};
 use serde_json::json;
 
 use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 pub static WATCHER_STATE: OnceLock<Arc<Mutex<watcher::FileWatcherState>>> = OnceLock::new();
 pub static PROMPT_CACHE: std::sync::LazyLock<Mutex<std::collections::HashMap<String, (std::time::SystemTime, String)>>> = std::sync::LazyLock::new(|| Mutex::new(std::collections::HashMap::new()));
 
+/// Request IDs between the reader thread and the main loop, for `notifications/cancelled`.
+static REQUESTS: std::sync::LazyLock<RequestTracker> = std::sync::LazyLock::new(RequestTracker::default);
+
+#[derive(Default)]
+struct RequestTracker {
+    /// Set when the client cancels the in-flight request; long operations poll it via `cancel_requested`.
+    cancel: AtomicBool,
+    state: Mutex<TrackedRequests>,
+}
+
+/// Only IDs still queued or running are kept, so cancels can't pile up.
+#[derive(Default)]
+struct TrackedRequests {
+    in_flight: Option<String>,
+    queued: std::collections::HashSet<String>,
+    /// Queued IDs cancelled before they started (a subset of `queued`).
+    cancelled: std::collections::HashSet<String>,
+}
+
+impl RequestTracker {
+    /// The reader thread handed `id` to the main loop.
+    fn queue(&self, id: String) {
+        if let Ok(mut s) = self.state.lock() { s.queued.insert(id); }
+    }
+
+    /// The main loop picks up `id`; false if it was cancelled while queued.
+    fn start(&self, id: Option<String>) -> bool {
+        let Ok(mut s) = self.state.lock() else { return true };
+        if let Some(id) = &id {
+            s.queued.remove(id);
+            if s.cancelled.remove(id) { return false; }
+        }
+        self.cancel.store(false, Ordering::Relaxed);
+        s.in_flight = id;
+        true
+    }
+
+    fn finish(&self) {
+        if let Ok(mut s) = self.state.lock() { s.in_flight = None; }
+    }
+
+    /// Flag `id` if it is running, remember it if queued; otherwise it already finished
+    /// (or never came) and the cancel is ignored.
+    fn cancel(&self, id: String) {
+        let Ok(mut s) = self.state.lock() else { return };
+        if s.in_flight.as_deref() == Some(id.as_str()) {
+            self.cancel.store(true, Ordering::Relaxed);
+        } else if s.queued.contains(&id) {
+            s.cancelled.insert(id);
+        }
+    }
+}
+
+/// Server start and requests handled so far, reported by `ping` when asked for telemetry.
+static STARTED: std::sync::LazyLock<std::time::Instant> = std::sync::LazyLock::new(std::time::Instant::now);
+static REQUESTS_SERVED: AtomicU64 = AtomicU64::new(0);
+
+/// clientInfo from the session's `initialize`; replaced if the client initializes again.
+static CLIENT_INFO: Mutex<Option<protocol::ClientInfo>> = Mutex::new(None);
+
+/// The connected MCP client, once it has sent `initialize`.
+pub fn client_info() -> Option<protocol::ClientInfo> {
+    CLIENT_INFO.lock().ok().and_then(|c| c.clone())
+}
+
+/// `source` value for the connected client, e.g. "claude-desktop".
+pub fn client_name() -> Option<String> {
+    client_info().map(|c| c.source()).filter(|n| !n.is_empty())
+}
+
+/// True once the client has sent `notifications/cancelled` for the request being handled.
+pub fn cancel_requested() -> bool {
+    REQUESTS.cancel.load(Ordering::Relaxed)
+}
+
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const SERVER_NAME: &str = "MemoryPilot";
+/// How often the server loop deletes expired memories (reads only filter them out).
+const EXPIRY_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(600);
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     if args.iter().any(|a| a == "--version" || a == "-v") { println!("MemoryPilot v{}", VERSION); return; }
     if args.iter().any(|a| a == "--help" || a == "-h") { print_help(); return; }
-    if args.iter().any(|a| a == "--migrate") { run_migrate(); return; }
-    if args.iter().any(|a| a == "--backfill") { run_backfill(); return; }
-    run_mcp_server();
+    if args.iter().any(|a| a == "--migrate") { run_migrate(args.iter().any(|a| a == "--dry-run"), args.iter().any(|a| a == "--dedup")); return; }
+    if args.iter().any(|a| a == "--backfill") {
+        run_backfill(args.iter().position(|a| a == "--project").and_then(|i| args.get(i + 1)).map(String::as_str));
+        return;
+    }
+    if args.iter().any(|a| a == "--reembed") { run_reembed(); return; }
+    if args.iter().any(|a| a == "--rebuild-index") { run_rebuild_index(); return; }
+    if let Some(i) = args.iter().position(|a| a == "--backup") { run_backup(args.get(i + 1)); return; }
+    let read_only = args.iter().any(|a| a == "--read-only")
+        || std::env::var("MEMORYPILOT_READ_ONLY").is_ok_and(|v| matches!(v.as_str(), "1" | "true" | "yes"));
+    run_mcp_server(read_only);
 }
 
-fn run_mcp_server() {
+fn run_mcp_server(read_only: bool) {
     if let Ok(cwd) = std::env::current_dir() {
         if let Some(state) = watcher::start_watcher(&cwd.to_string_lossy()) {
             let _ = WATCHER_STATE.set(state);
         }
     }
     
-    let db = match db::Database::open() {
-        Ok(d) => d, Err(e) => { eprintln!("DB error: {}", e); std::process::exit(1); }
+    let opened = if read_only { db::Database::open_read_only() } else { db::Database::open() };
+    let mut db = match opened {
+        Ok(d) => d, Err(e) => { log_at!(Error, "database open failed", error = e); std::process::exit(1); }
     };
-    let stdin = io::stdin();
-    let stdout = io::stdout();
-    let mut out = stdout.lock();    for line in stdin.lock().lines() {
-        let line = match line { Ok(l) if !l.trim().is_empty() => l, Ok(_) => continue, Err(_) => break };
-        let request: JsonRpcRequest = match serde_json::from_str(&line) {
-            Ok(r) => r,
-            Err(e) => {
-                let resp = JsonRpcResponse::error(None, -32700, format!("Parse: {}", e));
-                let _ = writeln!(out, "{}", serde_json::to_string(&resp).unwrap());
-                let _ = out.flush(); continue;
+    std::sync::LazyLock::force(&STARTED);
+    log_at!(Info, "server started", version = VERSION, read_only = read_only);
+    let _ = db.cleanup_expired();
+    let mut last_sweep = std::time::Instant::now();
+    let out = Arc::new(Mutex::new(io::stdout()));
+
+    // Reader thread: answers ping and records cancellations even while a long tool call
+    // (GC, backfill) is running, so the server never looks hung to the client.
+    let (tx, rx) = std::sync::mpsc::channel::<JsonRpcRequest>();
+    let reader_out = out.clone();
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = match line { Ok(l) if !l.trim().is_empty() => l, Ok(_) => continue, Err(_) => break };
+            let request: JsonRpcRequest = match serde_json::from_str(&line) {
+                Ok(r) => r,
+                Err(e) => {
+                    write_response(&reader_out, &JsonRpcResponse::error(None, -32700, format!("Parse: {}", e)));
+                    continue;
+                }
+            };
+            match request.method.as_str() {
+                "notifications/cancelled" => mark_cancelled(&request.params),
+                "ping" => write_response(&reader_out, &JsonRpcResponse::success(request.id.clone(), ping_result(&request.params))),
+                _ => {
+                    if let Some(id) = &request.id { REQUESTS.queue(id.to_string()); }
+                    if tx.send(request).is_err() { break }
+                }
+            }
+        }
+    });
+
+    // Waking up for the next sweep when idle, so expired memories go even without traffic
+    loop {
+        let request = match rx.recv_timeout(EXPIRY_SWEEP_INTERVAL.saturating_sub(last_sweep.elapsed())) {
+            Ok(request) => request,
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                let _ = db.cleanup_expired();
+                last_sweep = std::time::Instant::now();
+                continue;
             }
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
         };
-        let response = handle_request(&db, &request);
-        let _ = writeln!(out, "{}", serde_json::to_string(&response).unwrap());
+        // Cancelled before we got to it: the client no longer expects a response.
+        if !REQUESTS.start(request.id.as_ref().map(|id| id.to_string())) { continue; }
+        let started = std::time::Instant::now();
+        let response = handle_request(&mut db, &request);
+        REQUESTS_SERVED.fetch_add(1, Ordering::Relaxed);
+        log_request(&request, &response, started.elapsed());
+        REQUESTS.finish();
+        write_response(&out, &response);
+        if last_sweep.elapsed() >= EXPIRY_SWEEP_INTERVAL {
+            let _ = db.cleanup_expired();
+            last_sweep = std::time::Instant::now();
+        }
+    }
+}
+
+/// Info line per dispatched request: method, tool name for tools/call, latency, failure.
+/// Calls slower than a second are logged at warn.
+fn log_request(request: &JsonRpcRequest, response: &JsonRpcResponse, elapsed: std::time::Duration) {
+    let tool = request.params.get("name").and_then(|v| v.as_str()).filter(|_| request.method == "tools/call").unwrap_or("-");
+    let failed = response.error.is_some()
+        || response.result.as_ref().and_then(|r| r.get("isError")).and_then(|v| v.as_bool()).unwrap_or(false);
+    if elapsed.as_secs() >= 1 {
+        log_at!(Warn, "slow request", method = request.method, tool = tool, ms = elapsed.as_millis(), failed = failed);
+    } else {
+        log_at!(Info, "request", method = request.method, tool = tool, ms = elapsed.as_millis(), failed = failed);
+    }
+}
+
+fn write_response(out: &Mutex<io::Stdout>, response: &JsonRpcResponse) {
+    if let Ok(mut out) = out.lock() {
+        let _ = writeln!(out, "{}", serde_json::to_string(response).unwrap());
         let _ = out.flush();
     }
 }
 
-fn handle_request(db: &db::Database, req: &JsonRpcRequest) -> JsonRpcResponse {
+/// `ping` result: empty per spec, or liveness telemetry when the client sends any params.
+/// A `timestamp` param is echoed back so the caller can measure round-trip latency.
+fn ping_result(params: &serde_json::Value) -> serde_json::Value {
+    if params.as_object().is_none_or(|p| p.is_empty()) { return json!({}); }
+    let mut result = json!({
+        "uptime_secs": STARTED.elapsed().as_secs(),
+        "requests_served": REQUESTS_SERVED.load(Ordering::Relaxed),
+        "server_time": chrono::Utc::now().to_rfc3339(),
+    });
+    if let Some(ts) = params.get("timestamp") { result["timestamp"] = ts.clone(); }
+    result
+}
+
+/// Handle `notifications/cancelled`: flag the in-flight request, or remember a queued one.
+fn mark_cancelled(params: &serde_json::Value) {
+    if let Some(id) = params.get("requestId") { REQUESTS.cancel(id.to_string()); }
+}
+
+fn handle_request(db: &mut db::Database, req: &JsonRpcRequest) -> JsonRpcResponse {
     match req.method.as_str() {
-        "initialize" => JsonRpcResponse::success(req.id.clone(), json!({
+        "initialize" => {
+            let client = req.params.get("clientInfo").and_then(|v| serde_json::from_value::<protocol::ClientInfo>(v.clone()).ok());
+            if let Ok(mut info) = CLIENT_INFO.lock() { *info = client; }
+            JsonRpcResponse::success(req.id.clone(), json!({
             "protocolVersion": "2024-11-05",
             "capabilities": { "tools": { "listChanged": false } },
             "serverInfo": { "name": SERVER_NAME, "version": VERSION },
             "instructions": "IMPORTANT: At the start of every new conversation, call the 'recall' tool to load persistent memory context (project memories, preferences, critical facts, decisions). Pass working_dir for auto-detection. This ensures continuity across sessions."
-        })),
+            }))
+        }
         "notifications/initialized" => JsonRpcResponse::success(req.id.clone(), json!({})),
-        "tools/list" => JsonRpcResponse::success(req.id.clone(), tools::tool_definitions()),
+        "tools/list" => JsonRpcResponse::success(req.id.clone(), tools::tool_definitions(db)),
         "tools/call" => {
             let name = req.params.get("name").and_then(|v| v.as_str()).unwrap_or("");
             let args = req.params.get("arguments").cloned().unwrap_or(json!({}));
             JsonRpcResponse::success(req.id.clone(), tools::handle_tool_call(db, name, &args))
         }
-        "ping" => JsonRpcResponse::success(req.id.clone(), json!({})),
+        "ping" => JsonRpcResponse::success(req.id.clone(), ping_result(&req.params)),
         _ => JsonRpcResponse::error(req.id.clone(), -32601, format!("Unknown: {}", req.method)),
     }
 }
-fn run_migrate() {
+fn run_migrate(dry_run: bool, dedup: bool) {
     let db = match db::Database::open() { Ok(d) => d, Err(e) => { eprintln!("DB error: {}", e); std::process::exit(1); } };
-    match db.migrate_from_v1() {
-        Ok(n) => println!("✓ Migrated {} memories from v1 JSON to SQLite.", n),
+    if dry_run {
+        match db.preview_v1_migration() {
+            Ok(records) => {
+                for r in &records {
+                    let skip = if r.duplicate { " (duplicate, skipped)" } else { "" };
+                    println!("[{} -> {}] {}: {}{}", r.original_kind, r.kind, r.project.as_deref().unwrap_or("global"), r.content_head, skip);
+                }
+                let duplicates = records.iter().filter(|r| r.duplicate).count();
+                println!("Dry run: {} would be imported, {} duplicates.", records.len() - duplicates, duplicates);
+            }
+            Err(e) => { eprintln!("✗ Failed: {}", e); std::process::exit(1); }
+        }
+        return;
+    }
+    match db.migrate_from_v1(dedup) {
+        Ok(r) => println!("✓ Migrated v1 JSON to SQLite: {} added, {} merged, {} skipped.", r.added, r.merged, r.skipped),
         Err(e) => { eprintln!("✗ Failed: {}", e); std::process::exit(1); }
     }
 }
 
-fn run_backfill() {
+fn run_backfill(project: Option<&str>) {
     let db = match db::Database::open() { Ok(d) => d, Err(e) => { eprintln!("DB error: {}", e); std::process::exit(1); } };
-    match db.backfill_embeddings() {
+    match db.backfill_embeddings(project) {
         Ok(n) => println!("✓ Generated and saved embeddings for {} existing memories.", n),
         Err(e) => { eprintln!("✗ Failed: {}", e); std::process::exit(1); }
     }
 }
 
+fn run_reembed() {
+    let db = match db::Database::open() { Ok(d) => d, Err(e) => { eprintln!("DB error: {}", e); std::process::exit(1); } };
+    match db.reembed_all() {
+        Ok(n) => println!("✓ Recomputed embeddings for {} memories.", n),
+        Err(e) => { eprintln!("✗ Failed: {}", e); std::process::exit(1); }
+    }
+}
+
+fn run_rebuild_index() {
+    let db = match db::Database::open() { Ok(d) => d, Err(e) => { eprintln!("DB error: {}", e); std::process::exit(1); } };
+    match db.rebuild_fts() {
+        Ok(n) => println!("✓ Rebuilt full-text index for {} memories.", n),
+        Err(e) => { eprintln!("✗ Failed: {}", e); std::process::exit(1); }
+    }
+}
+
+fn run_backup(dest: Option<&String>) {
+    let dest = match dest { Some(d) if !d.starts_with("--") => d, _ => { eprintln!("Usage: MemoryPilot --backup <path>"); std::process::exit(1); } };
+    let db = match db::Database::open() { Ok(d) => d, Err(e) => { eprintln!("DB error: {}", e); std::process::exit(1); } };
+    match db.backup_to(std::path::Path::new(dest)) {
+        Ok(bytes) => println!("✓ Backed up database to {} ({} bytes).", dest, bytes),
+        Err(e) => { eprintln!("✗ Failed: {}", e); std::process::exit(1); }
+    }
+}
+
 fn print_help() {
     println!("MemoryPilot v{} — MCP memory server with SQLite FTS5", VERSION);
     println!();
     println!("USAGE:");
     println!("  MemoryPilot              Start MCP stdio server");
-    println!("  MemoryPilot --migrate    Migrate v1 JSON data to SQLite");
-    println!("  MemoryPilot --backfill   Compute missing TF-IDF embeddings");
+    println!("  MemoryPilot --read-only  Start server without write access (or MEMORYPILOT_READ_ONLY=1)");
+    println!("  MemoryPilot --migrate    Migrate v1 JSON data to SQLite (add --dry-run to preview,");
+    println!("                           --dedup to merge near-duplicates)");
+    println!("  MemoryPilot --backfill   Compute missing TF-IDF embeddings (add --project <name> to limit)");
+    println!("  MemoryPilot --reembed    Recompute all embeddings (clears drift warning)");
+    println!("  MemoryPilot --rebuild-index  Rebuild the FTS5 full-text index");
+    println!("  MemoryPilot --backup <path>  Write a consistent snapshot of the database");
     println!("  MemoryPilot --version    Show version");
     println!("  MemoryPilot --help       Show this help");
     println!();
-    println!("MCP TOOLS (20):");
+    println!("MCP TOOLS (54):");
     println!("  recall              Load all context in one shot (start here)");
     println!("  get_project_brain   Instant project summary (<1500 tokens)");
     println!("  search_memory       Hybrid BM25 + TF-IDF RRF search");
     println!("  get_file_context    Memories related to recently modified files");
+    println!("  explain_search      Search with a per-result ranking explanation");
+    println!("  search_projects     Projects mentioning a term, with counts");
+    println!("  get_related_projects Projects sharing entities with one project");
+    println!("  get_related_memories Graph walk from a memory, filtered by relation type");
     println!("  add_memory          Store with auto-dedup, entities, graph links");
     println!("  add_memories        Bulk add multiple memories in 1 call");
     println!("  get_memory          Retrieve by ID");
     println!("  update_memory       Update content/kind/tags/importance/TTL");
+    println!("  compact_memory      Condense one memory's paragraphs (preview, apply, undo)");
+    println!("  add_tags            Add tags to memories by ids/project/kind");
+    println!("  remove_tags         Remove tags from memories by ids/project/kind");
     println!("  delete_memory       Delete by ID (cascades links/entities)");
+    println!("  clone_memory        Copy a memory into another project");
+    println!("  get_recent          Most recently created or updated memories");
+    println!("  expiring_soon       Memories whose TTL runs out within N days");
+    println!("  get_gc_log          What run_gc expired or merged, and when");
+    println!("  metrics             Per-tool call counts and latency percentiles");
+    println!("  get_orphans         Memories with no graph links (or entities)");
+    println!("  get_embedding       Raw embedding vector of a memory (expose_embeddings)");
+    println!("  export_embeddings   {{id, vector}} JSONL per project (expose_embeddings)");
+    println!("  rebuild_memory_links Re-extract entities and links for one memory");
     println!("  list_memories       List with filters & pagination");
     println!("  get_project_context Full context in 1 call + auto-detect");
     println!("  register_project    Register project path for auto-detection");
     println!("  list_projects       List projects with counts");
+    println!("  merge_projects      Fold one project's memories into another");
+    println!("  register_kind       Register a custom memory kind");
     println!("  get_stats           Database statistics");
-    println!("  get_global_prompt   Auto-discover GLOBAL_PROMPT.md");
+    println!("  project_stats       One project's kinds, importance, activity, entities, links");
+    println!("  get_memory_timeline Monthly count, importance and kind breakdown");
+    println!("  cluster_memories    K-means thematic clusters with keyword labels");
+    println!("  suggest_tags        Tag suggestions from entities and similar memories");
+    println!("  get_global_prompt   Auto-discover GLOBAL_PROMPT.md (or a named prompt)");
+    println!("  list_global_prompts List named prompts in ~/.MemoryPilot/prompts/");
+    println!("  set_global_prompt   Write GLOBAL_PROMPT.md (home or project root)");
+    println!("  clear_global_prompt Delete GLOBAL_PROMPT.md");
     println!("  export_memories     Export as JSON or Markdown");
     println!("  set_config          Set config values");
+    println!("  set_active_scope    Switch workspace scope (isolates all reads/writes)");
     println!("  run_gc              Garbage collection: merge, clean, vacuum");
+    println!("  purge_unused_entities Drop orphaned (and optionally singleton) entities");
+    println!("  prune_links         Drop low-signal relates_to links");
+    println!("  validate_database   Integrity report; fix repairs FTS, orphans, embeddings");
+    println!("  reindex_project     Rebuild FTS rows and embeddings for one project");
+    println!("  backup_database     Snapshot the live database to a file");
+    println!("  restore_database    Swap in a verified backup without restarting");
     println!("  cleanup_expired     Remove expired memories");
     println!("  migrate_v1          Import from v1 JSON files");
+    println!("  about               Version, schema, features, DB path");
+    println!("  server_info         Same as about (capability probe)");
     println!();
     println!("STORAGE:  ~/.MemoryPilot/memory.db");
     println!("SEARCH:   Hybrid BM25 + TF-IDF RRF + graph boost + watcher context");
     println!("BUILT BY: SOFLUTION LTD");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_tracking() {
+        let tracker = RequestTracker::default();
+        let id = |n: i64| json!(n).to_string();
+        tracker.queue(id(1));
+        tracker.queue(id(2));
+        assert!(tracker.start(Some(id(1))));
+        tracker.cancel(id(1));
+        assert!(tracker.cancel.load(Ordering::Relaxed));
+        tracker.finish();
+
+        // Late and unknown cancels are dropped; a queued one skips its request once
+        tracker.cancel(id(1));
+        tracker.cancel(id(7));
+        tracker.cancel(id(2));
+        assert!(!tracker.start(Some(id(2))));
+        let state = tracker.state.lock().unwrap();
+        assert!(state.queued.is_empty() && state.cancelled.is_empty());
+    }
+}
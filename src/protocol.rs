@@ -21,6 +21,21 @@ pub struct JsonRpcResponse {
     pub error: Option<JsonRpcError>,
 }
 
+/// `clientInfo` sent by the client in `initialize`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClientInfo {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl ClientInfo {
+    /// Name as a `source` value: lowercased, whitespace as dashes ("Claude Desktop" -> "claude-desktop").
+    pub fn source(&self) -> String {
+        self.name.to_lowercase().split_whitespace().collect::<Vec<_>>().join("-")
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct JsonRpcError {
     pub code: i64,
@@ -1,6 +1,6 @@
 /// MCP Tool definitions and handlers for MemoryPilot v2.1.
 use serde_json::{json, Value};
-use crate::db::{Database, BulkItem};
+use crate::db::{Database, Memory, BulkItem, AddOptions, AddOutcome, SearchOptions, SearchResult, ListFilter, ContextLimits};
 use crate::protocol::{tool_result, tool_error};
 
 const VALID_KINDS: &[&str] = &[
@@ -8,7 +8,15 @@ const VALID_KINDS: &[&str] = &[
     "bug", "credential", "todo", "note",
 ];
 
-pub fn tool_definitions() -> Value {
+/// Built-in kinds plus any registered via `register_kind`.
+fn all_kinds(db: &Database) -> Vec<String> {
+    let mut kinds: Vec<String> = VALID_KINDS.iter().map(|k| k.to_string()).collect();
+    kinds.extend(db.custom_kinds().into_iter().map(|k| k.name));
+    kinds
+}
+
+pub fn tool_definitions(db: &Database) -> Value {
+    let kinds = all_kinds(db);
     json!({ "tools": [
         {
             "name": "recall",
@@ -18,30 +26,38 @@ pub fn tool_definitions() -> Value {
                 "properties": {
                     "project": { "type": ["string","null"], "description": "Project name (or null for auto-detect)" },
                     "working_dir": { "type": ["string","null"], "description": "Current working directory for project auto-detection" },
-                    "hints": { "type": ["string","null"], "description": "Keywords about current task for targeted memory search" }
+                    "hints": { "type": ["string","null"], "description": "Keywords about current task for targeted memory search" },
+                    "fuzzy": { "type": "boolean", "default": false, "description": "Tolerate spelling variants when auto-detecting the project (e.g. my-app vs myapp)" },
+                    "max_content_chars": { "type": ["integer","null"], "description": "Cap each memory's content to this many characters (ellipsis + truncated flag)" }
                 }
             }
         },
         {
             "name": "add_memory",
-            "description": "Store a new memory with dedup. If near-duplicate exists, merges instead of creating. Kinds: fact, preference, decision, pattern, snippet, bug, credential, todo, note.",
+            "description": "Store a new memory with dedup. If near-duplicate exists, merges instead of creating. Kinds: fact, preference, decision, pattern, snippet, bug, credential, todo, note, plus any registered via register_kind.",
             "inputSchema": {
                 "type": "object",
                 "properties": {
                     "content": { "type": "string", "description": "The memory content" },
-                    "kind": { "type": "string", "enum": VALID_KINDS, "default": "fact" },
+                    "kind": { "type": "string", "enum": kinds, "description": "Defaults to the project's default_kind (see recall), else fact" },
                     "project": { "type": ["string","null"], "description": "Project name or null for global" },
                     "tags": { "type": "array", "items": { "type": "string" }, "default": [] },
-                    "source": { "type": "string", "default": "cursor" },
+                    "source": { "type": "string", "description": "Provenance. Defaults to the default_source config, else the MCP client's name, else cursor" },
                     "importance": { "type": "integer", "minimum": 1, "maximum": 5, "default": 3, "description": "1=trivial, 3=normal, 5=critical" },
+                    "confidence": { "type": "number", "minimum": 0, "maximum": 1, "default": 1, "description": "How far to trust it, unlike importance (how much it matters): 1 for user-stated facts, lower for inferences. Slightly lowers search rank." },
                     "expires_at": { "type": ["string","null"], "description": "ISO date after which memory auto-deletes (e.g. 2025-06-01T00:00:00Z)" },
-                    "metadata": { "type": ["object","null"] }
+                    "metadata": { "type": ["object","null"] },
+                    "language": { "type": ["string","null"], "description": "Programming language of a snippet (rust, python, typescript, go, sql...). Enables code-aware indexing: camelCase/snake_case identifiers are split, keywords downweighted. Stored as metadata.language." },
+                    "verbatim": { "type": "boolean", "default": false, "description": "Store exactly as given (snippets, credentials): never merged with near-duplicates, never GC-compressed" },
+                    "auto_tag": { "type": ["boolean","null"], "description": "When no tags are given, tag with up to auto_tag_max (default 3) extracted tech/component entities, listed in metadata.auto_tags. Defaults to the auto_tag config (false)" },
+                    "idempotency_key": { "type": ["string","null"], "description": "Unique key for this write (per project). Retrying with the same key in the same project returns the original memory instead of adding again." },
+                    "override_importance": { "type": "boolean", "default": false, "description": "If this add merges into a near-duplicate, the given importance replaces the stored one (even if lower) instead of keeping the higher. Recorded in metadata.importance_changed." }
                 },
                 "required": ["content"]
             }
         },        {
             "name": "add_memories",
-            "description": "Bulk add multiple memories in one call. Each item supports dedup. Saves context window by batching 5-20 memories.",
+            "description": "Bulk add multiple memories in one call. Each item supports dedup. Saves context window by batching 5-20 memories. Returns created IDs, merged-into IDs and skipped items with reasons.",
             "inputSchema": {
                 "type": "object",
                 "properties": {
@@ -49,22 +65,25 @@ pub fn tool_definitions() -> Value {
                         "type": "object",
                         "properties": {
                             "content": { "type": "string" },
-                            "kind": { "type": "string", "default": "fact" },
+                            "kind": { "type": "string", "description": "Defaults to the project's default_kind, else fact" },
                             "project": { "type": ["string","null"] },
                             "tags": { "type": ["array","null"], "items": { "type": "string" } },
-                            "source": { "type": "string", "default": "cursor" },
+                            "source": { "type": "string", "description": "Defaults like add_memory's source" },
                             "importance": { "type": ["integer","null"] },
-                            "expires_at": { "type": ["string","null"] }
+                            "confidence": { "type": ["number","null"], "minimum": 0, "maximum": 1 },
+                            "expires_at": { "type": ["string","null"] },
+                            "idempotency_key": { "type": ["string","null"] }
                         },
                         "required": ["content"]
-                    }}
+                    }},
+                    "report": { "type": "string", "enum": ["summary", "full"], "default": "summary", "description": "full adds a per-item outcome list (added/merged/replayed/skipped with reason)" }
                 },
                 "required": ["memories"]
             }
         },
         {
             "name": "search_memory",
-            "description": "FTS5 BM25 full-text search weighted by importance. Supports prefix (svelt*) and multi-word queries. Auto-cleans expired.",
+            "description": "FTS5 BM25 full-text search weighted by importance. Supports prefix (svelt*) and multi-word queries. Expired memories are filtered out.",
             "inputSchema": {
                 "type": "object",
                 "properties": {
@@ -72,14 +91,86 @@ pub fn tool_definitions() -> Value {
                     "limit": { "type": "integer", "default": 10 },
                     "project": { "type": ["string","null"] },
                     "kind": { "type": ["string","null"] },
-                    "tags": { "type": ["array","null"], "items": { "type": "string" } }
+                    "tags": { "type": ["array","null"], "items": { "type": "string" } },
+                    "match_entities": { "type": "boolean", "default": false, "description": "Also match extracted entity values (tech, files, components) against query terms" },
+                    "fuzzy": { "type": "boolean", "default": false, "description": "Typo/variant-tolerant tag and entity matching (postgres ~ postgresql, k8s ~ kubernetes)" },
+                    "debug": { "type": "boolean", "default": false, "description": "Include matched_terms: which query terms appear in each result's content or tags" },
+                    "max_content_chars": { "type": ["integer","null"], "description": "Cap each result's content to this many characters; cut results get truncated: true (use get_memory for the full text)" },
+                    "project_fallback": { "type": "boolean", "default": false, "description": "If the project has fewer than limit hits, fill the rest from all projects; those results get fallback: true" },
+                    "exclude_kinds": { "type": ["array","null"], "items": { "type": "string" }, "description": "Skip these kinds" },
+                    "exclude_projects": { "type": ["array","null"], "items": { "type": "string" }, "description": "Skip these projects; global memories are kept" },
+                    "project_scope": { "type": "string", "enum": ["all","global","project"], "default": "all", "description": "all: no project filter unless project is set; global: only memories without a project; project: requires project" },
+                    "source": { "type": ["string","null"], "description": "Only memories from this source (e.g. cursor, claude-desktop)" },
+                    "exclude_sources": { "type": ["array","null"], "items": { "type": "string" }, "description": "Skip these sources, e.g. [\"gc_compressor\"] for GC summaries" },
+                    "min_importance": { "type": ["integer","null"], "minimum": 1, "maximum": 5, "description": "Importance floor; overrides the hide_below_importance config (pass 1 to see everything)" },
+                    "dedupe_results": { "type": "boolean", "default": false, "description": "Collapse near-identical results into the highest-importance one, which reports collapsed (count) and collapsed_ids" },
+                    "cursor": { "type": ["string","null"], "description": "next_cursor from the previous page (same query)" }
                 },
                 "required": ["query"]
             }
         },        {
+            "name": "explain_search",
+            "description": "Run the search_memory pipeline and explain, per result, why it ranked where it did: BM25 rank, vector similarity/rank, RRF base, importance, graph links, watcher and tag effects, final score. Plain-language narration plus the numbers. Does not bump access counts.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "limit": { "type": "integer", "default": 5 },
+                    "project": { "type": ["string","null"] },
+                    "kind": { "type": ["string","null"] },
+                    "tags": { "type": ["array","null"], "items": { "type": "string" } },
+                    "match_entities": { "type": "boolean", "default": false },
+                    "fuzzy": { "type": "boolean", "default": false }
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "search_projects",
+            "description": "Which projects mention a term? Runs the same full-text match as search_memory but returns only the distinct projects with match counts (null = global). Cheap way to pick a project filter first.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" }
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "get_related_memories",
+            "description": "Walk the knowledge graph from a memory: follow outgoing links (optionally only relation_types such as resolves or depends_on, checked at every hop) up to depth hops. Each result includes the path of relations that reached it.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string" },
+                    "relation_types": { "type": ["array","null"], "items": { "type": "string" }, "description": "e.g. [\"resolves\"] for what a decision resolves; default: all" },
+                    "depth": { "type": "integer", "default": 2, "minimum": 1, "maximum": 5 },
+                    "limit": { "type": "integer", "default": 20 },
+                    "max_content_chars": { "type": ["integer","null"] }
+                },
+                "required": ["id"]
+            }
+        },
+        {
+            "name": "get_related_projects",
+            "description": "Projects related to this one through shared extracted entities (APIs, files, components, tech), ranked by rarity-weighted overlap, with the number shared and the rarest shared values as examples.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "project": { "type": "string" },
+                    "limit": { "type": "integer", "default": 10 },
+                    "examples": { "type": "integer", "default": 5, "description": "Shared entity values listed per project" }
+                },
+                "required": ["project"]
+            }
+        },
+        {
             "name": "get_memory",
             "description": "Retrieve a single memory by ID.",
-            "inputSchema": { "type": "object", "properties": { "id": { "type": "string" } }, "required": ["id"] }
+            "inputSchema": { "type": "object", "properties": {
+                "id": { "type": "string" },
+                "include_expiry": { "type": "boolean", "default": false, "description": "Add expires_in_days (null = never expires)" }
+            }, "required": ["id"] }
         },
         {
             "name": "update_memory",
@@ -92,16 +183,148 @@ pub fn tool_definitions() -> Value {
                     "kind": { "type": ["string","null"] },
                     "tags": { "type": ["array","null"], "items": { "type": "string" } },
                     "importance": { "type": ["integer","null"], "minimum": 1, "maximum": 5 },
-                    "expires_at": { "type": ["string","null"] }
+                    "expires_at": { "type": ["string","null"] },
+                    "confidence": { "type": ["number","null"], "minimum": 0, "maximum": 1 }
+                },
+                "required": ["id"]
+            }
+        },
+        {
+            "name": "compact_memory",
+            "description": "Condense one bloated memory: its paragraphs go through the GC summarizer (keyword subject plus first sentence of each). Returns a before/after preview; apply: true writes it, keeping the old content as a revision that undo: true restores. Verbatim memories are refused.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string" },
+                    "apply": { "type": "boolean", "default": false },
+                    "undo": { "type": "boolean", "default": false, "description": "Restore the content from before the last compaction" },
+                    "force": { "type": "boolean", "default": false, "description": "With undo: restore even if the memory was edited after the compaction" }
                 },
                 "required": ["id"]
             }
         },
+        {
+            "name": "add_tags",
+            "description": "Bulk retag: add tags to every memory matching ids, project and/or kind (at least one filter), in one transaction. Tags are trimmed and lowercased. Returns the number of memories changed.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "ids": { "type": ["array","null"], "items": { "type": "string" } },
+                    "project": { "type": ["string","null"] },
+                    "kind": { "type": ["string","null"] },
+                    "tags": { "type": "array", "items": { "type": "string" } }
+                },
+                "required": ["tags"]
+            }
+        },
+        {
+            "name": "remove_tags",
+            "description": "Bulk retag: remove tags (case-insensitive) from every memory matching ids, project and/or kind (at least one filter), in one transaction. Returns the number of memories changed.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "ids": { "type": ["array","null"], "items": { "type": "string" } },
+                    "project": { "type": ["string","null"] },
+                    "kind": { "type": ["string","null"] },
+                    "tags": { "type": "array", "items": { "type": "string" } }
+                },
+                "required": ["tags"]
+            }
+        },
         {
             "name": "delete_memory",
             "description": "Delete a memory by ID.",
             "inputSchema": { "type": "object", "properties": { "id": { "type": "string" } }, "required": ["id"] }
         },
+        {
+            "name": "clone_memory",
+            "description": "Copy a memory into another project (e.g. a global pattern as a project's starting point), keeping the original. The copy gets a fresh ID, same content/kind/tags/importance, and goes through normal dedup and linking.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string" },
+                    "target_project": { "type": ["string","null"], "description": "Project to copy into (null = global)" }
+                },
+                "required": ["id", "target_project"]
+            }
+        },
+        {
+            "name": "get_embedding",
+            "description": "Raw stored embedding vector (f32 array) of a memory, for external clustering/visualization. Disabled unless config expose_embeddings=true.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "id": { "type": "string" } },
+                "required": ["id"]
+            }
+        },
+        {
+            "name": "export_embeddings",
+            "description": "Export {id, vector} JSONL for every embedded memory in a project (or all). Large output. Disabled unless config expose_embeddings=true.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "project": { "type": ["string","null"] } }
+            }
+        },
+        {
+            "name": "metrics",
+            "description": "Per-tool call counts, error counts and latency (p50/p95/max ms over the last 1000 calls) since the server started. Pass reset to start over after reading.",
+            "inputSchema": { "type": "object", "properties": { "reset": { "type": "boolean", "default": false } } }
+        },
+        {
+            "name": "get_gc_log",
+            "description": "Forensic trail of run_gc: which memories were removed (expired) or merged (with the summary's merged_into ID), their content at the time, newest first. Answers 'where did my memory go'.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "since": { "type": ["string","null"], "description": "Date or RFC 3339 timestamp (inclusive)" },
+                    "until": { "type": ["string","null"], "description": "Date or RFC 3339 timestamp (exclusive)" },
+                    "memory_id": { "type": ["string","null"], "description": "Entries for this memory, or merged into it" },
+                    "limit": { "type": "integer", "default": 50 }
+                }
+            }
+        },
+        {
+            "name": "get_recent",
+            "description": "What was just stored: the N most recently created or updated memories, newest first, in compact form (content capped at 200 chars).",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "count": { "type": "integer", "default": 10 },
+                    "by": { "type": "string", "enum": ["created","updated"], "default": "updated" },
+                    "project": { "type": ["string","null"] }
+                }
+            }
+        },
+        {
+            "name": "expiring_soon",
+            "description": "Memories whose TTL runs out within N days, soonest first, with expires_in_days, so critical ones can be refreshed (update_memory expires_at) before they silently vanish.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "days": { "type": "number", "default": 7 },
+                    "project": { "type": ["string","null"] },
+                    "limit": { "type": "integer", "default": 50 }
+                }
+            }
+        },
+        {
+            "name": "get_orphans",
+            "description": "Find isolated memories: no knowledge-graph links in either direction (and, with without_entities, no extracted entities). Often stale or badly tagged — candidates to retag, merge or delete. Oldest-updated first.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "project": { "type": ["string","null"] },
+                    "kind": { "type": ["string","null"] },
+                    "without_entities": { "type": "boolean", "default": false, "description": "Also require zero extracted entities" },
+                    "limit": { "type": "integer", "default": 50 }
+                }
+            }
+        },
+        {
+            "name": "rebuild_memory_links",
+            "description": "Re-extract one memory's entities and recompute its knowledge-graph links (e.g. after editing it). Returns the new entities and links.",
+            "inputSchema": { "type": "object", "properties": { "id": { "type": "string" } }, "required": ["id"] }
+        },
         {
             "name": "list_memories",
             "description": "List memories with optional filters and pagination.",
@@ -110,8 +333,15 @@ pub fn tool_definitions() -> Value {
                 "properties": {
                     "project": { "type": ["string","null"] },
                     "kind": { "type": ["string","null"] },
+                    "exclude_kinds": { "type": ["array","null"], "items": { "type": "string" }, "description": "Skip these kinds (e.g. [\"todo\"])" },
+                    "exclude_projects": { "type": ["array","null"], "items": { "type": "string" }, "description": "Skip these projects; global memories are kept" },
+                    "project_scope": { "type": "string", "enum": ["all","global","project"], "default": "all", "description": "all: no project filter unless project is set; global: only memories without a project; project: requires project" },
+                    "source": { "type": ["string","null"], "description": "Only memories from this source (e.g. cursor, claude-desktop)" },
+                    "exclude_sources": { "type": ["array","null"], "items": { "type": "string" }, "description": "Skip these sources, e.g. [\"gc_compressor\"] for GC summaries" },
+                    "cursor": { "type": ["string","null"], "description": "next_cursor from the previous page; replaces offset" },
                     "limit": { "type": "integer", "default": 20 },
-                    "offset": { "type": "integer", "default": 0 }
+                    "offset": { "type": "integer", "default": 0 },
+                    "include_expiry": { "type": "boolean", "default": false, "description": "Add expires_in_days to each memory (null = never expires)" }
                 }
             }
         },        {
@@ -121,7 +351,13 @@ pub fn tool_definitions() -> Value {
                 "type": "object",
                 "properties": {
                     "project": { "type": ["string","null"] },
-                    "working_dir": { "type": ["string","null"], "description": "Current directory for auto-detection" }
+                    "working_dir": { "type": ["string","null"], "description": "Current directory for auto-detection" },
+                    "fuzzy": { "type": "boolean", "default": false, "description": "Tolerate spelling variants when auto-detecting the project (e.g. my-app vs myapp)" },
+                    "max_tokens": { "type": ["integer","null"], "description": "Token budget (≈ 4 chars each); sections fill in order project, preferences, patterns, snippets. Default: no budget" },
+                    "project_limit": { "type": "integer", "default": 100 },
+                    "preferences_limit": { "type": "integer", "default": 50 },
+                    "patterns_limit": { "type": "integer", "default": 50 },
+                    "snippets_limit": { "type": "integer", "default": 20 }
                 }
             }
         },
@@ -133,24 +369,117 @@ pub fn tool_definitions() -> Value {
                 "properties": {
                     "project": { "type": ["string","null"], "description": "Project name (or null for auto-detect)" },
                     "working_dir": { "type": ["string","null"], "description": "Auto-detect project from path" },
-                    "max_tokens": { "type": "integer", "description": "Dynamic budget. Default is 1500" }
+                    "fuzzy": { "type": "boolean", "default": false, "description": "Tolerate spelling variants when auto-detecting the project (e.g. my-app vs myapp)" },
+                    "max_tokens": { "type": "integer", "description": "Dynamic budget. Default is 1500" },
+                    "recent_days": { "type": "integer", "default": 7, "description": "How far back recent_changes looks, in days" }
                 }
             }
         },
         {
             "name": "register_project",
-            "description": "Register project with filesystem path for auto-detection.",
+            "description": "Register project with filesystem path for auto-detection. Optionally set the project's default memory kind.",
             "inputSchema": {
                 "type": "object",
-                "properties": { "name": { "type": "string" }, "path": { "type": "string" }, "description": { "type": ["string","null"] } },
+                "properties": {
+                    "name": { "type": "string" }, "path": { "type": "string" }, "description": { "type": ["string","null"] },
+                    "default_kind": { "type": ["string","null"], "description": "Kind used by add_memory/add_memories when kind is omitted" }
+                },
                 "required": ["name", "path"]
             }
         },
         { "name": "list_projects", "description": "List all projects with memory counts.", "inputSchema": { "type": "object", "properties": {} } },
+        {
+            "name": "merge_projects",
+            "description": "Fold all memories of project `from` into project `into` in one transaction (memories, search index, graph entities), then remove the empty `from` project. Returns counts.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "from": { "type": "string" },
+                    "into": { "type": "string" },
+                    "dedup": { "type": "boolean", "default": false, "description": "Merge moved memories into near-duplicates already in `into` instead of moving them" }
+                },
+                "required": ["from", "into"]
+            }
+        },
+        {
+            "name": "register_kind",
+            "description": "Register a custom memory kind (e.g. meeting, requirement) usable by add_memory alongside the built-ins. Optional GC weight (0=never collect, 1.2=very expendable; default 0.5) and default importance.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Lowercase kind name (letters, digits, _ or -)" },
+                    "description": { "type": ["string","null"] },
+                    "gc_weight": { "type": ["number","null"], "minimum": 0 },
+                    "default_importance": { "type": ["integer","null"], "minimum": 1, "maximum": 5 }
+                },
+                "required": ["name"]
+            }
+        },
         { "name": "get_stats", "description": "Database statistics: totals, by kind, by project, expired count, db size.", "inputSchema": { "type": "object", "properties": {} } },
+        {
+            "name": "project_stats",
+            "description": "Knowledge health of one project: counts by kind and importance, average importance, last update/access, distinct entities, internal and cross-project links, embedding coverage, expired-but-pending count.",
+            "inputSchema": { "type": "object", "properties": { "project": { "type": "string", "description": "Project name, or __global__ (the get_stats by_project key) for memories without a project" } }, "required": ["project"] }
+        },
+        {
+            "name": "get_memory_timeline",
+            "description": "Longitudinal analytics: memories bucketed by creation month with count, average importance, importance histogram (1-5), kind distribution and GC-merged count. Chart-ready JSON, oldest month first.",
+            "inputSchema": { "type": "object", "properties": { "project": { "type": ["string","null"], "description": "Limit to one project" } } }
+        },
+        {
+            "name": "suggest_tags",
+            "description": "Suggest tags for new content or an existing memory: extracted tech/component entities plus tags common on its nearest neighbours (by embedding). Ranked with scores; apply them with update_memory.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string", "description": "Existing memory (its current tags are excluded)" },
+                    "content": { "type": "string", "description": "Content to tag when no id is given" },
+                    "project": { "type": ["string","null"], "description": "Neighbours from this project only (defaults to the memory's project)" },
+                    "limit": { "type": "integer", "default": 8 }
+                }
+            }
+        },
+        {
+            "name": "cluster_memories",
+            "description": "Thematic map of the knowledge base: k-means over stored embeddings. Returns clusters (largest first) with a keyword label, size, the memories nearest each centroid, and all member IDs.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "project": { "type": ["string","null"], "description": "Limit to one project" },
+                    "k": { "type": "integer", "default": 5, "description": "Number of clusters (capped at the number of memories)" },
+                    "representatives": { "type": "integer", "default": 3, "description": "Memories shown per cluster" }
+                }
+            }
+        },
         {
             "name": "get_global_prompt",
-            "description": "Load GLOBAL_PROMPT.md. Auto-scans: 1) configured path, 2) ~/.MemoryPilot/GLOBAL_PROMPT.md, 3) project root GLOBAL_PROMPT.md.",
+            "description": "Load GLOBAL_PROMPT.md. Auto-scans and concatenates: 1) configured path, 2) ~/.MemoryPilot/GLOBAL_PROMPT.md, 3) project root GLOBAL_PROMPT.md. Pass name to load one named prompt from ~/.MemoryPilot/prompts/ instead.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "name": { "type": ["string","null"], "description": "Named prompt (prompts/<name>.md), e.g. coding or writing. See list_global_prompts" },
+                    "project": { "type": ["string","null"] },
+                    "working_dir": { "type": ["string","null"] }
+                }
+            }
+        },
+        { "name": "list_global_prompts", "description": "List named prompts in ~/.MemoryPilot/prompts/ (name and size), selectable with get_global_prompt's name.", "inputSchema": { "type": "object", "properties": {} } },
+        {
+            "name": "set_global_prompt",
+            "description": "Write GLOBAL_PROMPT.md content. Defaults to ~/.MemoryPilot/GLOBAL_PROMPT.md; pass project, or a working_dir inside a registered project, to write that project-root prompt instead. Returns the path written.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "content": { "type": "string", "description": "Full prompt content (Markdown)" },
+                    "project": { "type": ["string","null"], "description": "Registered project whose root receives the prompt" },
+                    "working_dir": { "type": ["string","null"], "description": "Directory inside a registered project; its root receives the prompt" }
+                },
+                "required": ["content"]
+            }
+        },
+        {
+            "name": "clear_global_prompt",
+            "description": "Delete GLOBAL_PROMPT.md: ~/.MemoryPilot/GLOBAL_PROMPT.md by default, or the project-root prompt when project or working_dir is given. A prompt set via the global_prompt_path config is left alone.",
             "inputSchema": {
                 "type": "object",
                 "properties": {
@@ -166,17 +495,86 @@ pub fn tool_definitions() -> Value {
                 "type": "object",
                 "properties": {
                     "project": { "type": ["string","null"], "description": "Filter by project (null=all)" },
+                    "kind": { "type": ["string","null"], "description": "Only this kind (e.g. decision)" },
+                    "tags": { "type": ["array","null"], "items": { "type": "string" }, "description": "Only memories with any of these tags" },
+                    "min_importance": { "type": ["integer","null"], "minimum": 1, "maximum": 5 },
                     "format": { "type": "string", "enum": ["json", "markdown"], "default": "markdown" }
                 }
             }
         },
         {
             "name": "set_config",
-            "description": "Set a config value (e.g. global_prompt_path).",
+            "description": "Set a config value (e.g. global_prompt_path, gc_kind_weights_json).",
             "inputSchema": { "type": "object", "properties": { "key": { "type": "string" }, "value": { "type": "string" } }, "required": ["key", "value"] }
         },
-        { "name": "migrate_v1", "description": "Import from v1 JSON files. Skips duplicates.", "inputSchema": { "type": "object", "properties": {} } },
+        {
+            "name": "migrate_v1",
+            "description": "Import from v1 JSON files. Skips exact duplicates; with dedup, near-duplicates are merged too (slower). Use dry_run to preview records and kind remapping without writing.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "dry_run": { "type": "boolean", "default": false },
+                    "dedup": { "type": "boolean", "default": false, "description": "Fuzzy near-duplicate check and merge per record" }
+                }
+            }
+        },
         { "name": "cleanup_expired", "description": "Manually remove all expired memories.", "inputSchema": { "type": "object", "properties": {} } },
+        {
+            "name": "purge_unused_entities",
+            "description": "Graph maintenance: delete entity rows left behind by deleted memories and, optionally, entities referenced by only one memory (they create no links; tech and project entities are kept for the project brain). Returns counts.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "prune_singletons": { "type": "boolean", "description": "Also remove single-memory entities. Defaults to the purge_singleton_entities config (false)." },
+                    "dry_run": { "type": "boolean", "default": false }
+                }
+            }
+        },
+        {
+            "name": "validate_database",
+            "description": "Integrity check: FTS/memory mismatches, orphan links and entities, missing embeddings, expired rows not yet cleaned, invalid expires_at values. Each category reports a count and sample IDs. fix: true repairs the safe ones (FTS rebuild, orphan deletion, embedding backfill).",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "fix": { "type": "boolean", "default": false }
+                }
+            }
+        },
+        {
+            "name": "prune_links",
+            "description": "Graph maintenance: delete low-signal relates_to links — those justified only by entities shared by more than max_df memories, or joining memories whose importances sum below min_importance. Typed relations (resolves, depends_on, ...) are kept. Also runs as a run_gc step.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "max_df": { "type": "integer", "description": "Entity document-frequency ceiling (0 = off). Defaults to the gc_prune_link_max_df config (50)." },
+                    "min_importance": { "type": "integer", "description": "Minimum combined importance of the two memories (0 = off). Defaults to the gc_prune_link_min_importance config (4)." },
+                    "dry_run": { "type": "boolean", "default": false }
+                }
+            }
+        },
+        {
+            "name": "reindex_project",
+            "description": "Targeted maintenance: rebuild the FTS index rows and embeddings of one project's memories only, instead of a full-database --rebuild-index / --reembed. Returns the number reindexed.",
+            "inputSchema": { "type": "object", "properties": { "project": { "type": "string" } }, "required": ["project"] }
+        },
+        {
+            "name": "backup_database",
+            "description": "Write a consistent snapshot of the live database to a file using SQLite's online backup API (safe while the server runs). Use before a destructive run_gc. Returns the bytes written.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "path": { "type": "string", "description": "Destination file; parent directories are created" } },
+                "required": ["path"]
+            }
+        },
+        {
+            "name": "restore_database",
+            "description": "Replace the live database with a backup file (e.g. from backup_database) without restarting. The backup must pass SQLite integrity_check; otherwise nothing changes. Take a fresh backup first: the current contents are overwritten.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "path": { "type": "string" } },
+                "required": ["path"]
+            }
+        },
         { 
             "name": "run_gc", 
             "description": "Trigger Garbage Collection manually. Compresses old bugs/snippets and deletes expired.", 
@@ -185,10 +583,25 @@ pub fn tool_definitions() -> Value {
                 "properties": {
                     "age_days": { "type": "integer", "default": 30 },
                     "importance_threshold": { "type": "integer", "default": 3 },
+                    "protect_accessed_days": { "type": "integer", "default": 14, "description": "Never collect memories accessed within this many days (0 = no protection)" },
+                    "prune_links": { "type": "boolean", "description": "Also run prune_links (default: gc_prune_links config, off)" },
                     "dry_run": { "type": "boolean", "default": false }
                 } 
             } 
         },
+        {
+            "name": "set_active_scope",
+            "description": "Switch the active scope (workspace). Every read and write is confined to the active scope, so memories in other scopes never show up, even in global searches. Returns known scopes with memory counts.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "scope": { "type": "string", "description": "Scope name, e.g. 'personal' or 'client-acme' (default scope: 'default')" }
+                },
+                "required": ["scope"]
+            }
+        },
+        { "name": "about", "description": "Server introspection: name, version, schema version, enabled features, database path, whether the file watcher is active, embedding model/dimension and enabled config toggles.", "inputSchema": { "type": "object", "properties": {} } },
+        { "name": "server_info", "description": "Capability probe for connected agents; same output as about.", "inputSchema": { "type": "object", "properties": {} } },
         {
             "name": "get_file_context",
             "description": "Get memories related to recently modified files in the working directory. Uses the file watcher to know what you're working on.",
@@ -202,38 +615,197 @@ pub fn tool_definitions() -> Value {
         }
     ]})
 }
-/// Handle a tools/call request.
-pub fn handle_tool_call(db: &Database, name: &str, args: &Value) -> Value {
+/// Tools that write to the database or to the files MemoryPilot manages (global prompts);
+/// refused in read-only mode. `backup_database` only reads the database and writes a copy
+/// elsewhere, so it stays available.
+const MUTATING_TOOLS: &[&str] = &[
+    "add_memory", "add_memories", "update_memory", "add_tags", "remove_tags", "delete_memory", "clone_memory", "rebuild_memory_links", "register_project",
+    "merge_projects", "register_kind", "set_global_prompt", "clear_global_prompt", "set_config", "set_active_scope",
+    "restore_database", "cleanup_expired", "run_gc", "purge_unused_entities", "prune_links", "reindex_project",
+    "migrate_v1",
+];
+
+/// Whether a call would write. `run_gc`, `purge_unused_entities`, `prune_links` and `migrate_v1`
+/// only read when `dry_run` is set; `validate_database` only writes with `fix`, `compact_memory`
+/// with `apply` or `undo`.
+fn is_mutating(name: &str, args: &Value) -> bool {
+    let dry_run = args.get("dry_run").and_then(|v| v.as_bool()).unwrap_or(false);
     match name {
+        "run_gc" | "purge_unused_entities" | "prune_links" | "migrate_v1" => !dry_run,
+        "compact_memory" => ["apply", "undo"].iter().any(|k| args.get(*k).and_then(|v| v.as_bool()).unwrap_or(false)),
+        "validate_database" => args.get("fix").and_then(|v| v.as_bool()).unwrap_or(false),
+        _ => MUTATING_TOOLS.contains(&name),
+    }
+}
+
+/// Size arguments capped by the `max_limit` config, and the token budget capped by
+/// `max_tokens_limit`, so one call can't buffer an unbounded result set.
+const LIMIT_ARGS: &[&str] = &[
+    "limit", "count", "k", "representatives", "project_limit", "preferences_limit", "patterns_limit", "snippets_limit",
+];
+const NON_NEGATIVE_ARGS: &[&str] = &[
+    "limit", "count", "k", "representatives", "project_limit", "preferences_limit", "patterns_limit", "snippets_limit",
+    "offset", "max_tokens", "max_content_chars", "days",
+];
+
+/// Reject negative size arguments and clamp oversized ones. Returns the adjusted arguments
+/// (None when unchanged) and a note per clamped value.
+fn bound_args(db: &Database, args: &Value) -> Result<(Option<Value>, Vec<String>), Value> {
+    for key in NON_NEGATIVE_ARGS {
+        if args.get(*key).and_then(|v| v.as_f64()).is_some_and(|n| n < 0.0) {
+            return Err(tool_error(&format!("{} must not be negative", key)));
+        }
+    }
+    let max_limit: u64 = db.config_or("max_limit", 1000);
+    let max_tokens: u64 = db.config_or("max_tokens_limit", 32000);
+    let caps = LIMIT_ARGS.iter().map(|k| (*k, max_limit, "max_limit")).chain([("max_tokens", max_tokens, "max_tokens_limit")]);
+    let mut bounded = None;
+    let mut notes = Vec::new();
+    for (key, cap, setting) in caps {
+        if let Some(n) = args.get(key).and_then(|v| v.as_u64()).filter(|n| *n > cap) {
+            bounded.get_or_insert_with(|| args.clone())[key] = json!(cap);
+            notes.push(format!("{} clamped from {} to {} ({} config)", key, n, cap, setting));
+        }
+    }
+    Ok((bounded, notes))
+}
+
+/// Handle a tools/call request.
+pub fn handle_tool_call(db: &mut Database, name: &str, args: &Value) -> Value {
+    if db.is_read_only() && is_mutating(name, args) {
+        return tool_error(&format!("'{}' is not allowed: MemoryPilot is running in read-only mode", name));
+    }
+    let (bounded, notes) = match bound_args(db, args) { Ok(b) => b, Err(e) => return e };
+    let args = bounded.as_ref().unwrap_or(args);
+    let started = std::time::Instant::now();
+    match dispatch(db, name, args) {
+        Some(mut result) => {
+            let failed = result.get("isError").and_then(|v| v.as_bool()).unwrap_or(false);
+            record_metrics(name, started.elapsed(), failed);
+            if !notes.is_empty() {
+                if let Some(content) = result["content"].as_array_mut() {
+                    content.push(json!({ "type": "text", "text": format!("Note: {}", notes.join("; ")) }));
+                }
+            }
+            result
+        }
+        None => tool_error(&format!("Unknown tool: {}", name)),
+    }
+}
+
+/// Latency samples kept per tool for percentiles (the most recent ones).
+const METRICS_SAMPLES: usize = 1000;
+
+#[derive(Default)]
+struct ToolMetrics {
+    count: u64,
+    errors: u64,
+    samples_ms: std::collections::VecDeque<f64>,
+}
+
+/// Per-tool call counts and latencies since start (or the last `metrics` reset).
+static METRICS: std::sync::LazyLock<std::sync::Mutex<std::collections::BTreeMap<String, ToolMetrics>>> =
+    std::sync::LazyLock::new(Default::default);
+
+fn record_metrics(name: &str, elapsed: std::time::Duration, failed: bool) {
+    let Ok(mut metrics) = METRICS.lock() else { return };
+    let m = metrics.entry(name.to_string()).or_default();
+    m.count += 1;
+    if failed { m.errors += 1; }
+    if m.samples_ms.len() == METRICS_SAMPLES { m.samples_ms.pop_front(); }
+    m.samples_ms.push_back(elapsed.as_secs_f64() * 1000.0);
+}
+
+/// Nearest-rank percentile of sorted samples.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() { return 0.0; }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn handle_metrics(args: &Value) -> Value {
+    let Ok(mut metrics) = METRICS.lock() else { return tool_error("Metrics unavailable") };
+    let round = |ms: f64| (ms * 100.0).round() / 100.0;
+    let report: serde_json::Map<String, Value> = metrics.iter().map(|(tool, m)| {
+        let mut sorted: Vec<f64> = m.samples_ms.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        (tool.clone(), json!({
+            "count": m.count, "errors": m.errors,
+            "p50_ms": round(percentile(&sorted, 50.0)), "p95_ms": round(percentile(&sorted, 95.0)),
+            "max_ms": round(sorted.last().copied().unwrap_or(0.0)),
+        }))
+    }).collect();
+    if args.get("reset").and_then(|v| v.as_bool()).unwrap_or(false) { metrics.clear(); }
+    tool_result(&serde_json::to_string_pretty(&report).unwrap())
+}
+
+/// Route a tool call to its handler; None for an unknown tool.
+fn dispatch(db: &mut Database, name: &str, args: &Value) -> Option<Value> {
+    Some(match name {
         "recall" => handle_recall(db, args),
         "add_memory" => handle_add(db, args),
         "add_memories" => handle_add_bulk(db, args),
-        "search_memory" => handle_search(db, args),
+        "search_memory" => handle_search(db, args, false),
+        "explain_search" => handle_search(db, args, true),
+        "search_projects" => handle_search_projects(db, args),
+        "get_related_projects" => handle_related_projects(db, args),
+        "get_related_memories" => handle_related_memories(db, args),
         "get_memory" => handle_get(db, args),
         "update_memory" => handle_update(db, args),
+        "compact_memory" => handle_compact_memory(db, args),
         "delete_memory" => handle_delete(db, args),
+        "add_tags" => handle_bulk_tag(db, args, true),
+        "remove_tags" => handle_bulk_tag(db, args, false),
+        "clone_memory" => handle_clone(db, args),
+        "get_orphans" => handle_orphans(db, args),
+        "get_recent" => handle_recent(db, args),
+        "expiring_soon" => handle_expiring_soon(db, args),
+        "get_gc_log" => handle_gc_log(db, args),
+        "get_embedding" => handle_get_embedding(db, args),
+        "export_embeddings" => handle_export_embeddings(db, args),
+        "rebuild_memory_links" => handle_rebuild_memory_links(db, args),
         "list_memories" => handle_list(db, args),
         "get_project_context" => handle_project_context(db, args),
         "get_project_brain" => handle_get_project_brain(db, args),
         "register_project" => handle_register_project(db, args),
         "list_projects" => handle_list_projects(db),
+        "merge_projects" => handle_merge_projects(db, args),
+        "register_kind" => handle_register_kind(db, args),
         "get_stats" => handle_stats(db),
+        "project_stats" => handle_project_stats(db, args),
+        "get_memory_timeline" => handle_memory_timeline(db, args),
+        "cluster_memories" => handle_cluster_memories(db, args),
+        "suggest_tags" => handle_suggest_tags(db, args),
         "get_global_prompt" => handle_global_prompt(db, args),
+        "list_global_prompts" => handle_list_global_prompts(db),
+        "set_global_prompt" => handle_set_global_prompt(db, args),
+        "clear_global_prompt" => handle_clear_global_prompt(db, args),
         "export_memories" => handle_export(db, args),
         "set_config" => handle_set_config(db, args),
-        "migrate_v1" => handle_migrate(db),
+        "set_active_scope" => handle_set_active_scope(db, args),
+        "migrate_v1" => handle_migrate(db, args),
         "cleanup_expired" => handle_cleanup(db),
         "run_gc" => handle_run_gc(db, args),
+        "purge_unused_entities" => handle_purge_entities(db, args),
+        "prune_links" => handle_prune_links(db, args),
+        "validate_database" => handle_validate(db, args),
+        "reindex_project" => handle_reindex_project(db, args),
+        "backup_database" => handle_backup(db, args),
+        "restore_database" => handle_restore(db, args),
         "get_file_context" => handle_get_file_context(db, args),
-        _ => tool_error(&format!("Unknown tool: {}", name)),
-    }
+        "about" | "server_info" => handle_about(db),
+        "metrics" => handle_metrics(args),
+        _ => return None,
+    })
 }
 
 fn handle_recall(db: &Database, args: &Value) -> Value {
     let project = args.get("project").and_then(|v| v.as_str());
     let working_dir = args.get("working_dir").and_then(|v| v.as_str());
     let hints = args.get("hints").and_then(|v| v.as_str());
-    match db.recall(project, working_dir, hints) {
+    let fuzzy = args.get("fuzzy").and_then(|v| v.as_bool()).unwrap_or(false);
+    let max_content_chars = args.get("max_content_chars").and_then(|v| v.as_u64()).map(|n| n as usize);
+    match db.recall(project, working_dir, hints, fuzzy, max_content_chars) {
         Ok(ctx) => tool_result(&serde_json::to_string_pretty(&ctx).unwrap()),
         Err(e) => tool_error(&e),
     }
@@ -244,20 +816,64 @@ fn handle_add(db: &Database, args: &Value) -> Value {
         Some(c) if !c.trim().is_empty() => c,
         _ => return tool_error("content is required"),
     };
-    let kind = args.get("kind").and_then(|v| v.as_str()).unwrap_or("fact");
-    if !VALID_KINDS.contains(&kind) { return tool_error(&format!("Invalid kind '{}'. Valid: {:?}", kind, VALID_KINDS)); }
-    let project = args.get("project").and_then(|v| v.as_str());
-    let tags: Vec<String> = args.get("tags").and_then(|v| v.as_array())
-        .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect()).unwrap_or_default();
-    let source = args.get("source").and_then(|v| v.as_str()).unwrap_or("cursor");
-    let importance = args.get("importance").and_then(|v| v.as_i64()).unwrap_or(3) as i32;
-    let expires_at = args.get("expires_at").and_then(|v| v.as_str());
-    let metadata = args.get("metadata").filter(|v| !v.is_null());
+    let project = project_arg(db, args);
+    let project = project.as_deref();
+    let kind = match args.get("kind").and_then(|v| v.as_str()) {
+        Some(k) => k.to_string(),
+        None => db.default_kind(project),
+    };
+    let kinds = all_kinds(db);
+    if !kinds.contains(&kind) { return tool_error(&format!("Invalid kind '{}'. Valid: {:?}", kind, kinds)); }
+    let mut tags: Vec<String> = args.get("tags").and_then(|v| v.as_array())
+        .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect()).unwrap_or_default();
+    let source = args.get("source").and_then(|v| v.as_str()).map(String::from).unwrap_or_else(|| db.default_source());
+    let importance = args.get("importance").and_then(|v| v.as_i64()).map(|i| i as i32)
+        .or_else(|| db.custom_kind(&kind).and_then(|k| k.default_importance))
+        .unwrap_or(3);
+    let expires_at = args.get("expires_at").and_then(|v| v.as_str());
+    let mut metadata = args.get("metadata").filter(|v| !v.is_null()).cloned();
+    if let Some(language) = args.get("language").and_then(|v| v.as_str()).filter(|l| !l.trim().is_empty()) {
+        match metadata.get_or_insert_with(|| json!({})).as_object_mut() {
+            Some(m) => { m.insert("language".into(), json!(language.trim().to_lowercase())); }
+            None => return tool_error("language requires metadata to be an object"),
+        }
+    }
+    // Caller-provided tags always win; auto tags are recorded so they can be told apart
+    let auto_tag = args.get("auto_tag").and_then(|v| v.as_bool()).unwrap_or_else(|| db.config_or("auto_tag", false));
+    if auto_tag && tags.is_empty() {
+        let auto: Vec<String> = crate::graph::entity_tags(content, project).into_iter()
+            .take(db.config_or("auto_tag_max", 3)).collect();
+        if !auto.is_empty() {
+            match metadata.get_or_insert_with(|| json!({})).as_object_mut() {
+                Some(m) => { m.insert("auto_tags".into(), json!(auto)); }
+                None => return tool_error("auto_tag requires metadata to be an object"),
+            }
+            tags = auto;
+        }
+    }
+    let opts = AddOptions {
+        idempotency_key: args.get("idempotency_key").and_then(|v| v.as_str()).map(String::from),
+        // Only an explicitly passed importance may override the stored one
+        override_importance: args.get("override_importance").and_then(|v| v.as_bool()).unwrap_or(false)
+            && args.get("importance").is_some_and(|v| v.is_i64()),
+        verbatim: args.get("verbatim").and_then(|v| v.as_bool()).unwrap_or(false),
+        confidence: args.get("confidence").and_then(|v| v.as_f64()),
+    };
 
-    match db.add_memory(content, kind, project, &tags, source, importance, expires_at, metadata) {
-        Ok((mem, was_merged)) => {
+    match db.add_memory(content, &kind, project, &tags, &source, importance, expires_at, metadata.as_ref(), &opts) {
+        Ok((mem, outcome)) => {
             let mut result = serde_json::to_value(&mem).unwrap_or(json!({}));
-            if was_merged { result.as_object_mut().map(|o| o.insert("_merged".into(), json!(true))); }
+            match outcome {
+                AddOutcome::Merged => { result.as_object_mut().map(|o| o.insert("_merged".into(), json!(true))); }
+                AddOutcome::Replayed => { result.as_object_mut().map(|o| o.insert("_replayed".into(), json!(true))); }
+                AddOutcome::Added => {}
+            }
+            if db.question_filter() == "tag" && crate::db::looks_like_question(content) {
+                result.as_object_mut().map(|o| o.insert("_flagged".into(), json!("question")));
+            }
+            if let Some(warning) = db.source_warning(&source) {
+                result.as_object_mut().map(|o| o.insert("_warning".into(), json!(warning)));
+            }
             tool_result(&serde_json::to_string_pretty(&result).unwrap())
         }
         Err(e) => tool_error(&e),
@@ -268,22 +884,82 @@ fn handle_add_bulk(db: &Database, args: &Value) -> Value {
         Some(items) if !items.is_empty() => items,
         _ => return tool_error("memories array is required and cannot be empty"),
     };
+    let full = args.get("report").and_then(|v| v.as_str()) == Some("full");
     match db.add_memories_bulk(&items) {
-        Ok((added, merged, skipped)) => {
-            tool_result(&format!("Bulk complete: {} added, {} merged (dedup), {} skipped. Total processed: {}.",
-                added.len(), merged, skipped, items.len()))
+        Ok(report) => {
+            let mut output = json!({
+                "processed": items.len(),
+                "added": report.created.len(), "merged": report.merged.len(), "skipped": report.skipped.len(),
+                "skip_reasons": report.skip_reasons(),
+                "created_ids": report.created, "merged_into": report.merged,
+                "replayed_ids": report.replayed, "skipped_items": report.skipped,
+            });
+            if full { output["items"] = json!(report.items); }
+            tool_result(&serde_json::to_string_pretty(&output).unwrap())
         }
         Err(e) => tool_error(&e),
     }
 }
 
-fn handle_search(db: &Database, args: &Value) -> Value {
+/// Opaque pagination token: hex-encoded JSON of the position state.
+fn encode_cursor(state: &Value) -> String {
+    state.to_string().bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Inverse of `encode_cursor`; None for anything malformed.
+fn decode_cursor(cursor: &str) -> Option<Value> {
+    let bytes: Option<Vec<u8>> = (0..cursor.len()).step_by(2)
+        .map(|i| u8::from_str_radix(cursor.get(i..i + 2)?, 16).ok())
+        .collect();
+    serde_json::from_slice(&bytes?).ok()
+}
+
+/// Fingerprint of the filters a search page was ranked under, so a cursor cannot be replayed
+/// against a different result set.
+fn search_filters_hash(project: Option<&str>, kind: Option<&str>, tags: Option<&[String]>, opts: &SearchOptions) -> String {
+    use std::hash::{Hash, Hasher};
+    let filters = json!({
+        "project": project, "kind": kind, "tags": tags, "global_only": opts.global_only,
+        "min_importance": opts.min_importance, "source": opts.source, "exclude_kinds": opts.exclude_kinds,
+        "exclude_projects": opts.exclude_projects, "exclude_sources": opts.exclude_sources,
+        "match_entities": opts.match_entities, "fuzzy": opts.fuzzy,
+        "project_fallback": opts.project_fallback, "dedupe_results": opts.dedupe_results,
+    });
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    filters.to_string().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// String array argument `key`, empty when absent.
+fn string_list(args: &Value, key: &str) -> Vec<String> {
+    args.get(key).and_then(|v| v.as_array())
+        .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
+/// Apply the `project_scope` argument ("all", "global" or "project") to the resolved project.
+/// Returns the project to filter on and whether to keep only global (project-less) memories.
+/// Named apart from `scope`, which means the workspace scope of `set_active_scope`.
+fn project_scope<'a>(args: &Value, project: Option<&'a str>) -> Result<(Option<&'a str>, bool), Value> {
+    match args.get("project_scope").and_then(|v| v.as_str()).unwrap_or("all") {
+        "all" => Ok((project, false)),
+        "global" => Ok((None, true)),
+        "project" if project.is_some() => Ok((project, false)),
+        "project" => Err(tool_error("project_scope 'project' requires project")),
+        other => Err(tool_error(&format!("Invalid project_scope '{}': use all, global or project", other))),
+    }
+}
+
+/// search_memory, or explain_search when `explain` is set.
+fn handle_search(db: &Database, args: &Value, explain: bool) -> Value {
     let query = match args.get("query").and_then(|v| v.as_str()) {
         Some(q) if !q.trim().is_empty() => q,
         _ => return tool_error("query is required"),
     };
-    let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
-    let project = args.get("project").and_then(|v| v.as_str());
+    let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(if explain { 5 } else { 10 }) as usize;
+    let project = project_arg(db, args);
+    let project = project.as_deref();
+    let (project, global_only) = match project_scope(args, project) { Ok(s) => s, Err(e) => return e };
     let kind = args.get("kind").and_then(|v| v.as_str());
     let tags: Option<Vec<String>> = args.get("tags").and_then(|v| v.as_array())
         .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect());
@@ -296,29 +972,276 @@ fn handle_search(db: &Database, args: &Value) -> Value {
     }
     
     let wk_ref = if watcher_keywords.is_empty() { None } else { Some(watcher_keywords.as_slice()) };
+    let mut opts = SearchOptions {
+        match_entities: args.get("match_entities").and_then(|v| v.as_bool()).unwrap_or(false),
+        fuzzy: args.get("fuzzy").and_then(|v| v.as_bool()).unwrap_or(false),
+        explain,
+        debug: args.get("debug").and_then(|v| v.as_bool()).unwrap_or(false),
+        project_fallback: args.get("project_fallback").and_then(|v| v.as_bool()).unwrap_or(false),
+        exclude_kinds: string_list(args, "exclude_kinds"),
+        exclude_projects: string_list(args, "exclude_projects"),
+        source: args.get("source").and_then(|v| v.as_str()).map(String::from),
+        exclude_sources: string_list(args, "exclude_sources"),
+        // An explicit min_importance overrides the hide_below_importance floor `search` applies
+        min_importance: args.get("min_importance").and_then(|v| v.as_i64()).map(|m| m as i32),
+        global_only,
+        offset: 0,
+        dedupe_results: args.get("dedupe_results").and_then(|v| v.as_bool()).unwrap_or(false),
+    };
+    let filters = search_filters_hash(project, kind, tags.as_deref(), &opts);
+    // The offset is capped like any size argument: a forged cursor cannot force a huge ranking window
+    let max_offset: u64 = db.config_or("max_limit", 1000);
+    opts.offset = match args.get("cursor").and_then(|v| v.as_str()).filter(|_| !explain) {
+        Some(c) => match decode_cursor(c).filter(|c| c["q"] == query && c["f"] == filters.as_str()).and_then(|c| c["o"].as_u64()) {
+            Some(o) => o.min(max_offset) as usize,
+            None => return tool_error("Invalid cursor for this query"),
+        },
+        None => 0,
+    };
+    let offset = opts.offset;
     
-    match db.search(query, limit, project, kind, tags.as_deref(), wk_ref) {
-        Ok(results) => {
+    match db.search(query, limit, project, kind, tags.as_deref(), wk_ref, &opts) {
+        Ok(results) if explain => {
             let output = json!({ "query": query, "count": results.len(),
-                "results": results.iter().map(|r| json!({
-                    "id": r.memory.id, "content": r.memory.content, "kind": r.memory.kind,
-                    "project": r.memory.project, "tags": r.memory.tags, "score": r.score, "importance": r.memory.importance,
+                "formula": "final_score = rrf_base × importance_factor × confidence_factor × link_factor × watcher_factor × tag_factor",
+                "results": results.iter().enumerate().map(|(i, r)| json!({
+                    "rank": i + 1, "id": r.memory.id, "content": r.memory.content, "score": r.score,
+                    "matched_terms": r.matched_terms,
+                    "explanation": narrate(i + 1, r), "breakdown": r.explanation,
                 })).collect::<Vec<_>>()
             });
             tool_result(&serde_json::to_string_pretty(&output).unwrap())
         }
+        Ok(results) => {
+            let max_content_chars = args.get("max_content_chars").and_then(|v| v.as_u64()).map(|n| n as usize);
+            // A full page may have more behind it; the next one re-ranks and skips what was seen
+            let next_cursor = (limit > 0 && results.len() == limit)
+                .then(|| encode_cursor(&json!({ "q": query, "f": filters, "o": offset.saturating_add(limit) })));
+            let output = json!({ "query": query, "count": results.len(), "next_cursor": next_cursor,
+                "results": results.iter().map(|r| {
+                    let (content, truncated) = crate::db::truncate_content(&r.memory.content, max_content_chars);
+                    let mut item = json!({
+                        "id": r.memory.id, "content": content, "kind": r.memory.kind,
+                        "project": r.memory.project, "tags": r.memory.tags, "score": r.score, "importance": r.memory.importance,
+                        "confidence": r.memory.confidence,
+                    });
+                    if truncated { item["truncated"] = json!(true); }
+                    if r.fallback { item["fallback"] = json!(true); }
+                    if !r.collapsed.is_empty() {
+                        item["collapsed"] = json!(r.collapsed.len());
+                        item["collapsed_ids"] = json!(r.collapsed);
+                    }
+                    if let Some(terms) = &r.matched_terms { item["matched_terms"] = json!(terms); }
+                    item
+                }).collect::<Vec<_>>()
+            });
+            tool_result(&serde_json::to_string_pretty(&output).unwrap())
+        }
+        Err(e) => tool_error(&e),
+    }
+}
+
+/// Plain-language account of a result's `ScoreBreakdown`.
+fn narrate(rank: usize, r: &SearchResult) -> String {
+    let Some(b) = &r.explanation else { return String::new() };
+    let mut parts = vec![format!("#{} with score {:.4}.", rank, r.score)];
+    parts.push(match b.bm25_rank {
+        Some(n) => format!("Full-text (BM25) match ranked {}.", n),
+        None => "No full-text match.".into(),
+    });
+    parts.push(match b.vector_rank {
+        Some(n) => format!("Vector similarity {:.3}, ranked {}.", b.vector_similarity, n),
+        None => format!("Vector similarity {:.3}, not vector-ranked (no embedding, below vector_min_similarity, or outside the top 100).", b.vector_similarity),
+    });
+    if let Some(n) = b.entity_rank { parts.push(format!("Entity match ranked {}.", n)); }
+    parts.push(format!("Rank fusion gives a base of {:.4}.", b.rrf_base));
+    parts.push(format!("Importance {} scales it ×{:.2}.", r.memory.importance, b.importance_factor));
+    if b.confidence_factor < 1.0 {
+        parts.push(format!("Confidence {:.2} scales it ×{:.2}.", r.memory.confidence, b.confidence_factor));
+    }
+    if b.link_factor > 1.0 {
+        parts.push(format!("Incoming graph links boost it ×{:.2}.", b.link_factor));
+    } else if b.link_factor < 1.0 {
+        parts.push(format!("A 'deprecates' link penalises it ×{:.2}.", b.link_factor));
+    }
+    if b.watcher_matches > 0 {
+        parts.push(format!("{} recently edited file keyword(s) match: ×{:.2}.", b.watcher_matches, b.watcher_factor));
+    }
+    if b.tag_factor > 1.0 {
+        parts.push("A requested tag matches: ×1.5.".into());
+    } else if b.tag_factor < 1.0 {
+        parts.push("None of the requested tags match: ×0.1.".into());
+    }
+    parts.join(" ")
+}
+
+fn handle_search_projects(db: &Database, args: &Value) -> Value {
+    let query = match args.get("query").and_then(|v| v.as_str()) {
+        Some(q) if !q.trim().is_empty() => q,
+        _ => return tool_error("query is required"),
+    };
+    match db.projects_matching(query) {
+        Ok(projects) => {
+            let output = json!({ "query": query, "count": projects.len(),
+                "projects": projects.iter().map(|(p, n)| json!({ "project": p, "matches": n })).collect::<Vec<_>>()
+            });
+            tool_result(&serde_json::to_string_pretty(&output).unwrap())
+        }
+        Err(e) => tool_error(&e),
+    }
+}
+
+/// Hop cap for `get_related_memories`; node count is capped by `limit` (and `max_limit`).
+const MAX_RELATED_DEPTH: u64 = 5;
+
+fn handle_related_memories(db: &Database, args: &Value) -> Value {
+    let id = match args.get("id").and_then(|v| v.as_str()) { Some(i) => i, _ => return tool_error("id required") };
+    let relation_types = string_list(args, "relation_types");
+    let depth = args.get("depth").and_then(|v| v.as_u64()).unwrap_or(2).clamp(1, MAX_RELATED_DEPTH) as usize;
+    let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(20) as usize;
+    let max_content_chars = args.get("max_content_chars").and_then(|v| v.as_u64()).map(|n| n as usize);
+    match db.related_memories(id, &relation_types, depth, limit, max_content_chars) {
+        Ok(Some(related)) => tool_result(&serde_json::to_string_pretty(&json!({
+            "id": id, "relation_types": relation_types, "depth": depth, "count": related.len(), "related": related,
+        })).unwrap()),
+        Ok(None) => tool_error(&format!("Not found: {}", id)),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_related_projects(db: &Database, args: &Value) -> Value {
+    let project = match args.get("project").and_then(|v| v.as_str()) { Some(p) => p, _ => return tool_error("project required") };
+    let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
+    let examples = args.get("examples").and_then(|v| v.as_u64()).unwrap_or(5) as usize;
+    match db.related_projects(project, limit, examples) {
+        Ok(report) => tool_result(&serde_json::to_string_pretty(&report).unwrap()),
         Err(e) => tool_error(&e),
     }
 }
 
+/// A memory as JSON, plus `expires_in_days` (null = never expires) when `include_expiry` is set.
+fn with_expiry(mem: &Memory, include_expiry: bool) -> Value {
+    let mut value = serde_json::to_value(mem).unwrap_or(json!({}));
+    if include_expiry {
+        value["expires_in_days"] = json!(mem.expires_at.as_deref().and_then(crate::db::expires_in_days));
+    }
+    value
+}
+
 fn handle_get(db: &Database, args: &Value) -> Value {
     let id = match args.get("id").and_then(|v| v.as_str()) { Some(i) => i, _ => return tool_error("id required") };
+    let include_expiry = args.get("include_expiry").and_then(|v| v.as_bool()).unwrap_or(false);
     match db.get_memory(id) {
-        Ok(Some(mem)) => tool_result(&serde_json::to_string_pretty(&mem).unwrap()),
+        Ok(Some(mem)) => tool_result(&serde_json::to_string_pretty(&with_expiry(&mem, include_expiry)).unwrap()),
+        Ok(None) => tool_error(&format!("Not found: {}", id)),
+        Err(e) => tool_error(&e),
+    }
+}
+fn handle_clone(db: &Database, args: &Value) -> Value {
+    let id = match args.get("id").and_then(|v| v.as_str()) { Some(i) => i, _ => return tool_error("id required") };
+    let target = args.get("target_project").and_then(|v| v.as_str()).filter(|p| !p.trim().is_empty());
+    match db.clone_memory(id, target) {
+        Ok(Some((mem, outcome))) => {
+            let mut result = serde_json::to_value(&mem).unwrap_or(json!({}));
+            if outcome == AddOutcome::Merged { result.as_object_mut().map(|o| o.insert("_merged".into(), json!(true))); }
+            tool_result(&serde_json::to_string_pretty(&result).unwrap())
+        }
+        Ok(None) => tool_error(&format!("Not found: {}", id)),
+        Err(e) => tool_error(&e),
+    }
+}
+
+const EMBEDDINGS_DISABLED: &str = "Embedding access is disabled (vectors are large). Enable with set_config expose_embeddings=true.";
+
+fn handle_get_embedding(db: &Database, args: &Value) -> Value {
+    if !db.config_or("expose_embeddings", false) { return tool_error(EMBEDDINGS_DISABLED); }
+    let id = match args.get("id").and_then(|v| v.as_str()) { Some(i) => i, _ => return tool_error("id required") };
+    match db.get_embedding(id) {
+        Ok(Some(Some(vector))) => tool_result(&json!({ "id": id, "dims": vector.len(), "vector": vector }).to_string()),
+        Ok(Some(None)) => tool_error(&format!("No embedding stored for {} (run --backfill)", id)),
         Ok(None) => tool_error(&format!("Not found: {}", id)),
         Err(e) => tool_error(&e),
     }
 }
+
+fn handle_export_embeddings(db: &Database, args: &Value) -> Value {
+    if !db.config_or("expose_embeddings", false) { return tool_error(EMBEDDINGS_DISABLED); }
+    let project = args.get("project").and_then(|v| v.as_str());
+    match db.export_embeddings(project) {
+        Ok(jsonl) => tool_result(&jsonl),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_gc_log(db: &Database, args: &Value) -> Value {
+    let since = args.get("since").and_then(|v| v.as_str());
+    let until = args.get("until").and_then(|v| v.as_str());
+    let memory_id = args.get("memory_id").and_then(|v| v.as_str());
+    let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(50) as usize;
+    match db.gc_log(since, until, memory_id, limit) {
+        Ok(entries) => tool_result(&serde_json::to_string_pretty(&json!({ "count": entries.len(), "entries": entries })).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_expiring_soon(db: &Database, args: &Value) -> Value {
+    let days = args.get("days").and_then(|v| v.as_f64()).unwrap_or(7.0);
+    let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(50) as usize;
+    let project = args.get("project").and_then(|v| v.as_str());
+    match db.expiring_soon(days, project, limit) {
+        Ok(memories) => {
+            let items: Vec<Value> = memories.iter().map(|m| with_expiry(m, true)).collect();
+            tool_result(&serde_json::to_string_pretty(&json!({ "days": days, "count": items.len(), "memories": items })).unwrap())
+        }
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_recent(db: &Database, args: &Value) -> Value {
+    let count = args.get("count").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
+    let by_created = match args.get("by").and_then(|v| v.as_str()).unwrap_or("updated") {
+        "created" => true,
+        "updated" => false,
+        other => return tool_error(&format!("Invalid by '{}': use created or updated", other)),
+    };
+    let project = args.get("project").and_then(|v| v.as_str());
+    match db.recent(project, by_created, count) {
+        Ok(memories) => {
+            let items: Vec<Value> = memories.iter().map(|m| json!({
+                "id": m.id, "content": crate::db::truncate_content(&m.content, Some(200)).0,
+                "kind": m.kind, "project": m.project, "tags": m.tags, "importance": m.importance,
+                "at": if by_created { &m.created_at } else { &m.updated_at },
+            })).collect();
+            tool_result(&serde_json::to_string_pretty(&json!({ "by": if by_created { "created" } else { "updated" }, "count": items.len(), "memories": items })).unwrap())
+        }
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_orphans(db: &Database, args: &Value) -> Value {
+    let project = args.get("project").and_then(|v| v.as_str());
+    let kind = args.get("kind").and_then(|v| v.as_str());
+    let without_entities = args.get("without_entities").and_then(|v| v.as_bool()).unwrap_or(false);
+    let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(50) as usize;
+    match db.orphans(project, kind, without_entities, limit) {
+        Ok(memories) => tool_result(&serde_json::to_string_pretty(&json!({ "count": memories.len(), "memories": memories })).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_bulk_tag(db: &Database, args: &Value, add: bool) -> Value {
+    let tags = string_list(args, "tags");
+    if tags.is_empty() { return tool_error("tags required"); }
+    let ids = string_list(args, "ids");
+    let project = args.get("project").and_then(|v| v.as_str());
+    let kind = args.get("kind").and_then(|v| v.as_str());
+    let (to_add, to_remove) = if add { (tags.as_slice(), &[][..]) } else { (&[][..], tags.as_slice()) };
+    match db.bulk_tag(&ids, project, kind, to_add, to_remove) {
+        Ok(n) => tool_result(&serde_json::to_string_pretty(&json!({ "changed": n, "tags": tags })).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
 fn handle_update(db: &Database, args: &Value) -> Value {
     let id = match args.get("id").and_then(|v| v.as_str()) { Some(i) => i, _ => return tool_error("id required") };
     let content = args.get("content").and_then(|v| v.as_str());
@@ -327,13 +1250,32 @@ fn handle_update(db: &Database, args: &Value) -> Value {
         .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect());
     let importance = args.get("importance").and_then(|v| v.as_i64()).map(|i| i as i32);
     let expires_at = args.get("expires_at").and_then(|v| v.as_str());
-    match db.update_memory_full(id, content, kind, tags.as_deref(), importance, expires_at) {
+    let confidence = args.get("confidence").and_then(|v| v.as_f64());
+    match db.update_memory_full(id, content, kind, tags.as_deref(), importance, expires_at, confidence) {
         Ok(Some(mem)) => tool_result(&serde_json::to_string_pretty(&mem).unwrap()),
         Ok(None) => tool_error(&format!("Not found: {}", id)),
         Err(e) => tool_error(&e),
     }
 }
 
+fn handle_compact_memory(db: &Database, args: &Value) -> Value {
+    let id = match args.get("id").and_then(|v| v.as_str()) { Some(i) => i, _ => return tool_error("id required") };
+    if args.get("undo").and_then(|v| v.as_bool()).unwrap_or(false) {
+        let force = args.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+        return match db.undo_compaction(id, force) {
+            Ok(Some(mem)) => tool_result(&serde_json::to_string_pretty(&mem).unwrap()),
+            Ok(None) => tool_error(&format!("Not found: {}", id)),
+            Err(e) => tool_error(&e),
+        };
+    }
+    let apply = args.get("apply").and_then(|v| v.as_bool()).unwrap_or(false);
+    match db.compact_memory(id, apply) {
+        Ok(Some(report)) => tool_result(&serde_json::to_string_pretty(&report).unwrap()),
+        Ok(None) => tool_error(&format!("Not found: {}", id)),
+        Err(e) => tool_error(&e),
+    }
+}
+
 fn handle_delete(db: &Database, args: &Value) -> Value {
     let id = match args.get("id").and_then(|v| v.as_str()) { Some(i) => i, _ => return tool_error("id required") };
     match db.delete_memory(id) {
@@ -343,14 +1285,66 @@ fn handle_delete(db: &Database, args: &Value) -> Value {
     }
 }
 
+fn handle_rebuild_memory_links(db: &Database, args: &Value) -> Value {
+    let id = match args.get("id").and_then(|v| v.as_str()) { Some(i) => i, _ => return tool_error("id required") };
+    let mem = match db.get_memory(id) {
+        Ok(Some(m)) => m,
+        Ok(None) => return tool_error(&format!("Not found: {}", id)),
+        Err(e) => return tool_error(&e),
+    };
+    if let Err(e) = db.rebuild_links(&mem) { return tool_error(&e); }
+    let output = json!({
+        "id": id,
+        "entities": db.memory_entities(id).into_iter().map(|(k, v)| json!({ "kind": k, "value": v })).collect::<Vec<_>>(),
+        "links": db.memory_links(id).into_iter().map(|(s, t, r)| json!({ "source_id": s, "target_id": t, "relation": r })).collect::<Vec<_>>(),
+    });
+    tool_result(&serde_json::to_string_pretty(&output).unwrap())
+}
+
+/// `project` argument: a string as given, an explicit null for global, and when omitted
+/// the default project (`MEMORYPILOT_PROJECT` / `default_project` config).
+fn project_arg(db: &Database, args: &Value) -> Option<String> {
+    match args.get("project") {
+        Some(Value::String(p)) => Some(p.clone()),
+        Some(Value::Null) => None,
+        _ => db.default_project(),
+    }
+}
+
 fn handle_list(db: &Database, args: &Value) -> Value {
-    let project = args.get("project").and_then(|v| v.as_str());
+    let project = project_arg(db, args);
+    let project = project.as_deref();
+    let (project, global_only) = match project_scope(args, project) { Ok(s) => s, Err(e) => return e };
     let kind = args.get("kind").and_then(|v| v.as_str());
     let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(20) as usize;
-    let offset = args.get("offset").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
-    match db.list_memories(project, kind, limit, offset) {
-        Ok((memories, total)) => {
-            tool_result(&serde_json::to_string_pretty(&json!({"total":total,"count":memories.len(),"offset":offset,"memories":memories})).unwrap())
+    let after = match args.get("cursor").and_then(|v| v.as_str()) {
+        Some(c) => match decode_cursor(c).and_then(|c| Some((c["u"].as_str()?.to_string(), c["i"].as_str()?.to_string()))) {
+            Some(after) => Some(after),
+            None => return tool_error("Invalid cursor"),
+        },
+        None => None,
+    };
+    // A cursor already marks the position
+    let offset = if after.is_some() { 0 } else { args.get("offset").and_then(|v| v.as_u64()).unwrap_or(0) as usize };
+    let filter = ListFilter {
+        exclude_kinds: string_list(args, "exclude_kinds"),
+        exclude_projects: string_list(args, "exclude_projects"),
+        source: args.get("source").and_then(|v| v.as_str()).map(String::from),
+        exclude_sources: string_list(args, "exclude_sources"),
+        global_only,
+        after,
+        ..Default::default()
+    };
+    // One extra row tells whether another page exists
+    match db.list_memories_filtered(project, kind, &filter, limit + 1, offset) {
+        Ok((mut memories, total)) => {
+            let next_cursor = (memories.len() > limit).then(|| {
+                memories.truncate(limit);
+                memories.last().map(|m| encode_cursor(&json!({ "u": m.updated_at, "i": m.id })))
+            }).flatten();
+            let include_expiry = args.get("include_expiry").and_then(|v| v.as_bool()).unwrap_or(false);
+            let memories: Vec<Value> = memories.iter().map(|m| with_expiry(m, include_expiry)).collect();
+            tool_result(&serde_json::to_string_pretty(&json!({"total":total,"count":memories.len(),"offset":offset,"next_cursor":next_cursor,"memories":memories})).unwrap())
         }
         Err(e) => tool_error(&e),
     }
@@ -358,23 +1352,35 @@ fn handle_list(db: &Database, args: &Value) -> Value {
 fn handle_project_context(db: &Database, args: &Value) -> Value {
     let project = args.get("project").and_then(|v| v.as_str());
     let working_dir = args.get("working_dir").and_then(|v| v.as_str());
-    match db.get_project_context(project, working_dir) {
+    let fuzzy = args.get("fuzzy").and_then(|v| v.as_bool()).unwrap_or(false);
+    let defaults = ContextLimits::default();
+    let limit = |key: &str, default: usize| args.get(key).and_then(|v| v.as_u64()).map(|n| n as usize).unwrap_or(default);
+    let limits = ContextLimits {
+        max_tokens: args.get("max_tokens").and_then(|v| v.as_u64()).map(|n| n as usize),
+        project: limit("project_limit", defaults.project),
+        preferences: limit("preferences_limit", defaults.preferences),
+        patterns: limit("patterns_limit", defaults.patterns),
+        snippets: limit("snippets_limit", defaults.snippets),
+    };
+    match db.get_project_context(project, working_dir, fuzzy, &limits) {
         Ok(ctx) => tool_result(&serde_json::to_string_pretty(&ctx).unwrap()),
         Err(e) => tool_error(&e),
     }
 }
 
 fn handle_get_project_brain(db: &Database, args: &Value) -> Value {
-    let proj_detect = args.get("working_dir").and_then(|v| v.as_str()).and_then(|wd| db.detect_project(wd).ok().flatten());
+    let fuzzy = args.get("fuzzy").and_then(|v| v.as_bool()).unwrap_or(false);
+    let proj_detect = args.get("working_dir").and_then(|v| v.as_str()).and_then(|wd| db.detect_project(wd, fuzzy).ok().flatten());
     
-    let project = match args.get("project").and_then(|v| v.as_str()).or_else(|| proj_detect.as_deref()) {
+    let project = match args.get("project").and_then(|v| v.as_str()).or(proj_detect.as_deref()) {
         Some(p) => p,
         None => return tool_error("project or working_dir is required, and project must be found"),
     };
     
     let max_tokens = args.get("max_tokens").and_then(|v| v.as_u64()).map(|v| v as usize);
+    let recent_days = args.get("recent_days").and_then(|v| v.as_i64()).filter(|d| *d > 0);
     
-    match db.get_project_brain(project, max_tokens) {
+    match db.get_project_brain(project, max_tokens, recent_days) {
         Ok(brain) => tool_result(&serde_json::to_string_pretty(&brain).unwrap()),
         Err(e) => tool_error(&e),
     }
@@ -384,7 +1390,12 @@ fn handle_register_project(db: &Database, args: &Value) -> Value {
     let name = match args.get("name").and_then(|v| v.as_str()) { Some(n) => n, _ => return tool_error("name required") };
     let path = match args.get("path").and_then(|v| v.as_str()) { Some(p) => p, _ => return tool_error("path required") };
     let desc = args.get("description").and_then(|v| v.as_str());
-    match db.register_project(name, path, desc) {
+    let default_kind = args.get("default_kind").and_then(|v| v.as_str());
+    if let Some(k) = default_kind {
+        let kinds = all_kinds(db);
+        if !kinds.iter().any(|c| c == k) { return tool_error(&format!("Invalid default_kind '{}'. Valid: {:?}", k, kinds)); }
+    }
+    match db.register_project(name, path, desc, default_kind) {
         Ok(proj) => tool_result(&serde_json::to_string_pretty(&proj).unwrap()),
         Err(e) => tool_error(&e),
     }
@@ -397,6 +1408,32 @@ fn handle_list_projects(db: &Database) -> Value {
     }
 }
 
+fn handle_merge_projects(db: &Database, args: &Value) -> Value {
+    let from = match args.get("from").and_then(|v| v.as_str()) { Some(f) if !f.trim().is_empty() => f, _ => return tool_error("from required") };
+    let into = match args.get("into").and_then(|v| v.as_str()) { Some(i) if !i.trim().is_empty() => i, _ => return tool_error("into required") };
+    let dedup = args.get("dedup").and_then(|v| v.as_bool()).unwrap_or(false);
+    match db.merge_projects(from, into, dedup) {
+        Ok(report) => tool_result(&serde_json::to_string_pretty(&report).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_register_kind(db: &Database, args: &Value) -> Value {
+    let name = match args.get("name").and_then(|v| v.as_str()) { Some(n) => n.trim(), _ => return tool_error("name required") };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-') {
+        return tool_error("name must be lowercase letters, digits, '_' or '-'");
+    }
+    if VALID_KINDS.contains(&name) { return tool_error(&format!("'{}' is a built-in kind", name)); }
+    let description = args.get("description").and_then(|v| v.as_str());
+    let gc_weight = args.get("gc_weight").and_then(|v| v.as_f64());
+    if gc_weight.is_some_and(|w| w < 0.0) { return tool_error("gc_weight must be >= 0"); }
+    let default_importance = args.get("default_importance").and_then(|v| v.as_i64()).map(|i| i as i32);
+    match db.register_kind(name, description, gc_weight, default_importance) {
+        Ok(kind) => tool_result(&serde_json::to_string_pretty(&json!({ "registered": kind, "kinds": all_kinds(db) })).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
 fn handle_stats(db: &Database) -> Value {
     match db.stats() {
         Ok(s) => tool_result(&serde_json::to_string_pretty(&s).unwrap()),
@@ -404,7 +1441,57 @@ fn handle_stats(db: &Database) -> Value {
     }
 }
 
+fn handle_project_stats(db: &Database, args: &Value) -> Value {
+    let project = match args.get("project").and_then(|v| v.as_str()) { Some(p) => p, _ => return tool_error("project required") };
+    match db.project_stats(project) {
+        Ok(Some(s)) => tool_result(&serde_json::to_string_pretty(&s).unwrap()),
+        Ok(None) => tool_error(&format!("Not found: {}", project)),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_memory_timeline(db: &Database, args: &Value) -> Value {
+    match db.memory_timeline(args.get("project").and_then(|v| v.as_str())) {
+        Ok(t) => tool_result(&serde_json::to_string_pretty(&t).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_suggest_tags(db: &Database, args: &Value) -> Value {
+    let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(8) as usize;
+    let project_arg = args.get("project").and_then(|v| v.as_str());
+    let result = if let Some(id) = args.get("id").and_then(|v| v.as_str()) {
+        let mem = match db.get_memory(id) {
+            Ok(Some(m)) => m,
+            Ok(None) => return tool_error(&format!("Not found: {}", id)),
+            Err(e) => return tool_error(&e),
+        };
+        db.suggest_tags(&mem.content, project_arg.or(mem.project.as_deref()), Some(id), &mem.tags, limit)
+    } else if let Some(content) = args.get("content").and_then(|v| v.as_str()) {
+        db.suggest_tags(content, project_arg, None, &[], limit)
+    } else {
+        return tool_error("id or content required");
+    };
+    match result {
+        Ok(s) => tool_result(&serde_json::to_string_pretty(&s).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_cluster_memories(db: &Database, args: &Value) -> Value {
+    let project = args.get("project").and_then(|v| v.as_str());
+    let k = args.get("k").and_then(|v| v.as_u64()).unwrap_or(5).max(1) as usize;
+    let representatives = args.get("representatives").and_then(|v| v.as_u64()).unwrap_or(3) as usize;
+    match db.cluster_memories(project, k, representatives) {
+        Ok(c) => tool_result(&serde_json::to_string_pretty(&c).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
 fn handle_global_prompt(db: &Database, args: &Value) -> Value {
+    if let Some(name) = args.get("name").and_then(|v| v.as_str()) {
+        return match db.named_prompt(name) { Ok(prompt) => tool_result(&prompt), Err(e) => tool_error(&e) };
+    }
     let project = args.get("project").and_then(|v| v.as_str());
     let working_dir = args.get("working_dir").and_then(|v| v.as_str());
     match db.get_global_prompt(project, working_dir) {
@@ -413,10 +1500,42 @@ fn handle_global_prompt(db: &Database, args: &Value) -> Value {
     }
 }
 
+fn handle_list_global_prompts(db: &Database) -> Value {
+    let prompts: Vec<Value> = db.list_named_prompts().into_iter().map(|(name, bytes)| json!({ "name": name, "bytes": bytes })).collect();
+    tool_result(&serde_json::to_string_pretty(&json!({ "count": prompts.len(), "prompts": prompts })).unwrap())
+}
+
+fn handle_set_global_prompt(db: &Database, args: &Value) -> Value {
+    let content = match args.get("content").and_then(|v| v.as_str()) { Some(c) => c, _ => return tool_error("content required") };
+    let project = args.get("project").and_then(|v| v.as_str());
+    let working_dir = args.get("working_dir").and_then(|v| v.as_str());
+    match db.set_global_prompt(content, project, working_dir) {
+        Ok(path) => tool_result(&format!("Global prompt written to {}", path.display())),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_clear_global_prompt(db: &Database, args: &Value) -> Value {
+    let project = args.get("project").and_then(|v| v.as_str());
+    let working_dir = args.get("working_dir").and_then(|v| v.as_str());
+    match db.clear_global_prompt(project, working_dir) {
+        Ok(Some(path)) => tool_result(&format!("Global prompt removed: {}", path.display())),
+        Ok(None) => tool_result("No global prompt file to remove."),
+        Err(e) => tool_error(&e),
+    }
+}
+
 fn handle_export(db: &Database, args: &Value) -> Value {
     let project = args.get("project").and_then(|v| v.as_str());
+    let kind = args.get("kind").and_then(|v| v.as_str());
+    let filter = ListFilter {
+        tags: args.get("tags").and_then(|v| v.as_array())
+            .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect()),
+        min_importance: args.get("min_importance").and_then(|v| v.as_i64()).map(|i| i as i32),
+        ..Default::default()
+    };
     let format = args.get("format").and_then(|v| v.as_str()).unwrap_or("markdown");
-    match db.export_memories(project, format) {
+    match db.export_memories(project, kind, &filter, format) {
         Ok(output) => tool_result(&output),
         Err(e) => tool_error(&e),
     }
@@ -425,15 +1544,65 @@ fn handle_export(db: &Database, args: &Value) -> Value {
 fn handle_set_config(db: &Database, args: &Value) -> Value {
     let key = match args.get("key").and_then(|v| v.as_str()) { Some(k) => k, _ => return tool_error("key required") };
     let value = match args.get("value").and_then(|v| v.as_str()) { Some(v) => v, _ => return tool_error("value required") };
+    if key == "question_filter" && !["off", "reject", "tag"].contains(&value) {
+        return tool_error("question_filter must be one of: off, reject, tag");
+    }
+    if key == "source_allowlist_mode" && !["reject", "warn"].contains(&value) {
+        return tool_error("source_allowlist_mode must be one of: reject, warn");
+    }
+    if key == "embedding_weights_json" && serde_json::from_str::<crate::embedding::EmbeddingWeights>(value).is_err() {
+        return tool_error("embedding_weights_json must be a JSON object like {\"position\": [0.7, 0.5], \"bigram\": 0.3}");
+    }
+    if key == "gc_kind_weights_json" && serde_json::from_str::<std::collections::HashMap<String, f64>>(value).is_err() {
+        return tool_error("gc_kind_weights_json must be a JSON object of kind -> weight, e.g. {\"note\": 1.0}");
+    }
+    if key == "fts_token_chars" {
+        return match db.set_fts_token_chars(value) {
+            Ok(n) => tool_result(&format!("Config '{}' = '{}' (FTS index rebuilt, {} rows)", key, value, n)),
+            Err(e) => tool_error(&e),
+        };
+    }
+    if (key == "embedding_synonyms" || key == "query_synonyms") && value.parse::<bool>().is_err() {
+        return tool_error(&format!("{} must be true or false", key));
+    }
     match db.set_config(key, value) {
+        // Applied only once stored, so the engine never runs weights the config doesn't hold
+        Ok(()) if key == "embedding_synonyms" || key == "query_synonyms" || key == "embedding_weights_json" => {
+            db.load_embedding_weights();
+            let note = if key == "query_synonyms" { "" } else { " (stored embeddings keep the old setting until `MemoryPilot --reembed`)" };
+            tool_result(&format!("Config '{}' = '{}'{}", key, value, note))
+        }
         Ok(()) => tool_result(&format!("Config '{}' = '{}'", key, value)),
         Err(e) => tool_error(&e),
     }
 }
 
-fn handle_migrate(db: &Database) -> Value {
-    match db.migrate_from_v1() {
-        Ok(count) => tool_result(&format!("Migrated {} memories from v1 to SQLite.", count)),
+fn handle_set_active_scope(db: &Database, args: &Value) -> Value {
+    let scope = match args.get("scope").and_then(|v| v.as_str()).map(str::trim) {
+        Some(s) if !s.is_empty() => s, _ => return tool_error("scope required"),
+    };
+    let previous = db.active_scope();
+    if let Err(e) = db.set_config("active_scope", scope) { return tool_error(&e); }
+    let scopes: serde_json::Map<String, Value> = db.scopes().into_iter().map(|(s, n)| (s, json!(n))).collect();
+    tool_result(&serde_json::to_string_pretty(&json!({ "active_scope": scope, "previous_scope": previous, "scopes": scopes })).unwrap())
+}
+
+fn handle_migrate(db: &Database, args: &Value) -> Value {
+    if args.get("dry_run").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return match db.preview_v1_migration() {
+            Ok(records) => {
+                let duplicates = records.iter().filter(|r| r.duplicate).count();
+                tool_result(&serde_json::to_string_pretty(&json!({
+                    "dry_run": true, "would_import": records.len() - duplicates,
+                    "duplicates": duplicates, "records": records,
+                })).unwrap())
+            }
+            Err(e) => tool_error(&format!("Migration preview failed: {}", e)),
+        };
+    }
+    let dedup = args.get("dedup").and_then(|v| v.as_bool()).unwrap_or(false);
+    match db.migrate_from_v1(dedup) {
+        Ok(report) => tool_result(&format!("Migrated v1 to SQLite: {} added, {} merged, {} skipped.", report.added, report.merged, report.skipped)),
         Err(e) => tool_error(&format!("Migration failed: {}", e)),
     }
 }
@@ -446,9 +1615,11 @@ fn handle_cleanup(db: &Database) -> Value {
 }
 
 fn handle_run_gc(db: &Database, args: &Value) -> Value {
-    let mut config = crate::gc::GcConfig::default();
+    let mut config = db.gc_config();
     if let Some(age) = args.get("age_days").and_then(|v| v.as_i64()) { config.age_days = age; }
     if let Some(imp) = args.get("importance_threshold").and_then(|v| v.as_i64()) { config.importance_threshold = imp as i32; }
+    if let Some(days) = args.get("protect_accessed_days").and_then(|v| v.as_i64()) { config.protect_accessed_days = days; }
+    if let Some(prune) = args.get("prune_links").and_then(|v| v.as_bool()) { config.prune_links = prune; }
     let dry_run = args.get("dry_run").and_then(|v| v.as_bool()).unwrap_or(false);
     
     match db.run_gc(&config, dry_run) {
@@ -457,6 +1628,70 @@ fn handle_run_gc(db: &Database, args: &Value) -> Value {
     }
 }
 
+fn handle_purge_entities(db: &Database, args: &Value) -> Value {
+    let prune_singletons = args.get("prune_singletons").and_then(|v| v.as_bool())
+        .unwrap_or_else(|| db.config_or("purge_singleton_entities", false));
+    let dry_run = args.get("dry_run").and_then(|v| v.as_bool()).unwrap_or(false);
+    match db.purge_unused_entities(prune_singletons, dry_run) {
+        Ok(report) => tool_result(&serde_json::to_string_pretty(&report).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_validate(db: &Database, args: &Value) -> Value {
+    let fix = args.get("fix").and_then(|v| v.as_bool()).unwrap_or(false);
+    match db.validate(fix) {
+        Ok(report) => tool_result(&serde_json::to_string_pretty(&report).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_prune_links(db: &Database, args: &Value) -> Value {
+    let config = db.gc_config();
+    let max_df = args.get("max_df").and_then(|v| v.as_i64()).unwrap_or(config.prune_link_max_df);
+    let min_importance = args.get("min_importance").and_then(|v| v.as_i64()).map(|i| i as i32).unwrap_or(config.prune_link_min_importance);
+    let dry_run = args.get("dry_run").and_then(|v| v.as_bool()).unwrap_or(false);
+    match db.prune_links(max_df, min_importance, dry_run) {
+        Ok(n) => tool_result(&serde_json::to_string_pretty(&json!({ "links_pruned": n, "max_df": max_df, "min_importance": min_importance, "dry_run": dry_run })).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_reindex_project(db: &Database, args: &Value) -> Value {
+    let project = match args.get("project").and_then(|v| v.as_str()) {
+        Some(p) if !p.trim().is_empty() => p,
+        _ => return tool_error("project is required"),
+    };
+    match db.reindex_project(project) {
+        Ok(n) => tool_result(&serde_json::to_string_pretty(&json!({ "project": project, "reindexed": n })).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_backup(db: &Database, args: &Value) -> Value {
+    let path = match args.get("path").and_then(|v| v.as_str()) {
+        Some(p) if !p.trim().is_empty() => std::path::Path::new(p),
+        _ => return tool_error("path is required"),
+    };
+    match db.backup_to(path) {
+        Ok(bytes) => tool_result(&serde_json::to_string_pretty(&json!({ "path": path.display().to_string(), "bytes": bytes })).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
+fn handle_restore(db: &mut Database, args: &Value) -> Value {
+    let path = match args.get("path").and_then(|v| v.as_str()) {
+        Some(p) if !p.trim().is_empty() => std::path::Path::new(p),
+        _ => return tool_error("path is required"),
+    };
+    match db.restore_from(path) {
+        Ok(count) => tool_result(&serde_json::to_string_pretty(&json!({
+            "restored_from": path.display().to_string(), "memories": count, "schema_version": db.schema_version(),
+        })).unwrap()),
+        Err(e) => tool_error(&e),
+    }
+}
+
 fn handle_get_file_context(db: &Database, args: &Value) -> Value {
     let _wd = match args.get("working_dir").and_then(|v| v.as_str()) {
         Some(w) => w,
@@ -475,7 +1710,7 @@ fn handle_get_file_context(db: &Database, args: &Value) -> Value {
     }
     
     let query = keywords.join(" ");
-    match db.search(&query, 10, None, None, None, Some(&keywords)) {
+    match db.search(&query, 10, None, None, None, Some(&keywords), &SearchOptions::default()) {
         Ok(results) => {
             let output = json!({ 
                 "recent_file_keywords": keywords, 
@@ -489,4 +1724,143 @@ fn handle_get_file_context(db: &Database, args: &Value) -> Value {
         }
         Err(e) => tool_error(&e),
     }
-}
\ No newline at end of file
+}
+
+/// Features active in this build/session, reported by `about`.
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = vec![
+        "hybrid_search", "knowledge_graph", "gc", "project_brain",
+        "custom_kinds", "idempotency_keys", "request_cancellation", "scopes",
+    ];
+    if crate::WATCHER_STATE.get().is_some() { features.push("file_watcher"); }
+    features
+}
+
+/// How many config keys are set, and the boolean toggles currently on.
+fn config_summary(db: &Database) -> Value {
+    let entries = db.config_entries();
+    let enabled: Vec<&str> = entries.iter()
+        .filter(|(_, v)| matches!(v.trim().to_lowercase().as_str(), "true" | "on" | "yes"))
+        .map(|(k, _)| k.as_str()).collect();
+    json!({ "keys_set": entries.len(), "toggles_enabled": enabled.len(), "enabled": enabled })
+}
+
+fn handle_about(db: &Database) -> Value {
+    let info = json!({
+        "name": crate::SERVER_NAME,
+        "version": crate::VERSION,
+        "schema_version": db.schema_version(),
+        "active_scope": db.active_scope(),
+        "read_only": db.is_read_only(),
+        "client": crate::client_info(),
+        "default_source": db.default_source(),
+        "enabled_features": enabled_features(),
+        "db_path": db.path().display().to_string(),
+        "watcher_active": crate::WATCHER_STATE.get().is_some(),
+        "embedding": { "model": "hashed-tfidf", "dimension": crate::embedding::VECTOR_DIM,
+            "weights_changed": db.embedding_drift()["weights_changed"] },
+        "config": config_summary(db),
+    });
+    tool_result(&serde_json::to_string_pretty(&info).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Call a tool and parse the JSON in its first text block.
+    fn call(db: &mut Database, name: &str, args: Value) -> Value {
+        let result = handle_tool_call(db, name, &args);
+        let text = result["content"][0]["text"].as_str().unwrap_or_default();
+        serde_json::from_str(text).unwrap_or_else(|_| json!({ "error": text, "isError": result["isError"] }))
+    }
+
+    #[test]
+    fn test_search_results_include_confidence() {
+        let mut db = Database::open_in_memory().unwrap();
+        call(&mut db, "add_memory", json!({ "content": "Webhooks are retried three times", "confidence": 0.6 }));
+        let out = call(&mut db, "search_memory", json!({ "query": "webhooks retried" }));
+        assert_eq!(out["results"][0]["confidence"], 0.6);
+    }
+
+    #[test]
+    fn test_cursors_page_through_every_row_once() {
+        let mut db = Database::open_in_memory().unwrap();
+        let mut ids: Vec<String> = (0..7).map(|i| {
+            call(&mut db, "add_memory", json!({ "content": format!("Deploy checklist step {} for the pipeline", i), "verbatim": true }))["id"]
+                .as_str().unwrap().to_string()
+        }).collect();
+        ids.sort();
+
+        for (tool, key, first) in [("list_memories", "memories", json!({ "limit": 3 })),
+                                   ("search_memory", "results", json!({ "query": "deploy pipeline", "limit": 3 }))] {
+            let (mut seen, mut args) = (Vec::new(), first.clone());
+            loop {
+                let page = call(&mut db, tool, args.clone());
+                seen.extend(page[key].as_array().unwrap().iter().map(|m| m["id"].as_str().unwrap().to_string()));
+                let Some(cursor) = page["next_cursor"].as_str() else { break };
+                args = first.clone();
+                args["cursor"] = json!(cursor);
+            }
+            seen.sort();
+            assert_eq!(seen, ids, "{}", tool);
+        }
+    }
+
+    #[test]
+    fn test_malformed_cursor_is_a_clean_error() {
+        let mut db = Database::open_in_memory().unwrap();
+        for args in [json!({ "cursor": "zz" }), json!({ "cursor": "7b7d" })] {
+            let out = handle_tool_call(&mut db, "list_memories", &args);
+            assert_eq!(out["isError"], true);
+            assert_eq!(out["content"][0]["text"], "Invalid cursor");
+        }
+        let other_query = encode_cursor(&json!({ "q": "something else", "o": 3 }));
+        let out = handle_tool_call(&mut db, "search_memory", &json!({ "query": "deploy", "cursor": other_query }));
+        assert_eq!(out["isError"], true);
+        assert_eq!(out["content"][0]["text"], "Invalid cursor for this query");
+
+        for i in 0..3 {
+            call(&mut db, "add_memory", json!({ "content": format!("Deploy runbook part {} for the api", i), "kind": "fact", "verbatim": true }));
+        }
+        let first = call(&mut db, "search_memory", json!({ "query": "deploy runbook", "limit": 1 }));
+        let cursor = first["next_cursor"].as_str().unwrap();
+        let out = handle_tool_call(&mut db, "search_memory", &json!({ "query": "deploy runbook", "kind": "fact", "cursor": cursor }));
+        assert_eq!(out["content"][0]["text"], "Invalid cursor for this query");
+
+        // A forged offset is capped instead of sizing the ranking window
+        let filters = search_filters_hash(None, None, None, &SearchOptions::default());
+        let forged = encode_cursor(&json!({ "q": "deploy runbook", "f": filters, "o": u64::MAX }));
+        let out = call(&mut db, "search_memory", json!({ "query": "deploy runbook", "limit": 1, "cursor": forged }));
+        assert_eq!(out["count"], 0);
+    }
+
+    #[test]
+    fn test_bound_args_clamps_oversized_values() {
+        let db = Database::open_in_memory().unwrap();
+        let (bounded, notes) = bound_args(&db, &json!({ "limit": 5000, "max_tokens": 100000, "offset": 20 })).unwrap();
+        let bounded = bounded.unwrap();
+        assert_eq!((bounded["limit"].as_u64(), bounded["max_tokens"].as_u64(), bounded["offset"].as_u64()), (Some(1000), Some(32000), Some(20)));
+        assert_eq!(notes, ["limit clamped from 5000 to 1000 (max_limit config)", "max_tokens clamped from 100000 to 32000 (max_tokens_limit config)"]);
+
+        assert!(bound_args(&db, &json!({ "limit": 1000, "max_tokens": 32000 })).unwrap().0.is_none());
+        assert!(bound_args(&db, &json!({ "limit": -1 })).is_err());
+    }
+
+    #[test]
+    fn test_read_only_refuses_mutating_tools() {
+        let dir = std::env::temp_dir().join(format!("memorypilot-tools-read-only-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("memory.db");
+        drop(Database::open_at(&path).unwrap());
+        let mut db = Database::open_read_only_at(&path).unwrap();
+        let refused = handle_tool_call(&mut db, "add_memory", &json!({ "content": "Should not be stored" }));
+        assert_eq!(refused["isError"], true);
+        assert!(refused["content"][0]["text"].as_str().unwrap().contains("read-only mode"));
+        // Dry runs and reads still go through
+        assert_ne!(handle_tool_call(&mut db, "run_gc", &json!({ "dry_run": true }))["isError"], true);
+        assert_eq!(call(&mut db, "list_memories", json!({}))["total"], 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
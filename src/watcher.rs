@@ -68,12 +68,17 @@ pub fn start_watcher(dir: &str) -> Option<Arc<Mutex<FileWatcherState>>> {
             }
         }) {
             Ok(w) => w,
-            Err(_) => return,
+            Err(e) => {
+                log_at!(Warn, "file watcher unavailable", error = e);
+                return;
+            }
         };
         
-        if watcher.watch(&dir_path, RecursiveMode::Recursive).is_err() {
+        if let Err(e) = watcher.watch(&dir_path, RecursiveMode::Recursive) {
+            log_at!(Warn, "file watcher failed", dir = dir_path.display(), error = e);
             return;
         }
+        log_at!(Info, "file watcher started", dir = dir_path.display());
 
         for event in rx {
             if !matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) { continue; }
@@ -91,6 +96,7 @@ pub fn start_watcher(dir: &str) -> Option<Arc<Mutex<FileWatcherState>>> {
                     }
                 }
                 
+                log_at!(Debug, "file changed", path = path_str);
                 if let Ok(mut s) = state_clone.lock() {
                     s.push(FileChange {
                         path: path_str.to_string(),